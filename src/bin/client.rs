@@ -1,16 +1,22 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dialoguer::Select;
-use std::io::{stdout, Write};
+use std::path::PathBuf;
 
+use candy_game::client::account::{login, register, show_profile, Login};
 use candy_game::client::create::create_game;
-use candy_game::client::join::join_game;
+use candy_game::client::join::{join_game, spectate_game};
+use candy_game::client::leaderboard::show_leaderboard;
 use candy_game::client::list::list_games;
+use candy_game::client::replay::replay;
+use candy_game::client::theme::Theme;
+use candy_game::game::maze_loader::MazeLibrary;
 
 /// Candy game
 /// Collect all candies and exit the map
 /// Connect to a remote server and execute one of commands
-/// Controls after joining: arrows - move, space - shoot
+/// Controls after joining: r - ready, arrows - move, space - shoot
+/// Controls when spectating: Tab/Shift-Tab - follow players, arrows - move camera, f - free camera
 /// Press Esc/q to exit
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -19,18 +25,68 @@ struct Args {
     /// Server address
     #[arg(short = 's', default_value_t = String::from("localhost:3030"))]
     server: String,
+    /// Theme: default, ascii, mono or a theme file, ascii if the terminal doesn't expect UTF-8
+    #[arg(long, global = true)]
+    theme: Option<String>,
+    /// Directory with additional maze files, used instead of downloading mazes the server has
+    /// the same
+    #[arg(long, global = true)]
+    maze_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Play back a recorded game
+    /// Controls: space - pause, arrows - step, b/f - seek, +/- - speed
+    #[clap(verbatim_doc_comment)]
+    Replay {
+        /// Recording file
+        file: PathBuf,
+        /// Playback speed multiplier
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let theme = match &args.theme {
+        Some(name) => Theme::load(name)?,
+        None => Theme::detect(),
+    };
+    let mazes = match &args.maze_dir {
+        Some(dir) => MazeLibrary::with_dir(dir)?,
+        None => MazeLibrary::builtin(),
+    };
 
-    const COMMANDS: [&str; 4] = ["list", "create", "join", "quit"];
-    let mut stdout = stdout();
+    if let Some(Command::Replay { file, speed }) = &args.command {
+        return replay(file, *speed, &theme).await;
+    }
+
+    const COMMANDS: [&str; 9] = [
+        "list",
+        "create",
+        "join",
+        "spectate",
+        "register",
+        "login",
+        "profile",
+        "leaderboard",
+        "quit",
+    ];
+    let mut account: Option<Login> = None;
 
     loop {
+        let prompt = match &account {
+            Some(login) => format!("Game menu (logged in as {})", login.name),
+            None => "Game menu".to_string(),
+        };
         let command: usize = Select::new()
-            .with_prompt("Game menu")
+            .with_prompt(prompt)
             .items(&COMMANDS)
             .default(0)
             .interact()?;
@@ -40,17 +96,22 @@ async fn main() -> Result<()> {
         } else if command == 1 {
             create_game(&args.server).await?;
         } else if command == 2 {
-            join_game(&args.server).await?;
+            join_game(&args.server, &mazes, account.as_ref(), &theme).await?;
+        } else if command == 3 {
+            spectate_game(&args.server, &mazes, &theme).await?;
+        } else if command == 4 {
+            register(&args.server).await?;
+        } else if command == 5 {
+            if let Some(login) = login(&args.server).await? {
+                account = Some(login);
+            }
+        } else if command == 6 {
+            show_profile(&args.server, account.as_ref()).await?;
+        } else if command == 7 {
+            show_leaderboard(&args.server).await?;
         } else {
             break;
         }
-
-        write!(
-            stdout,
-            "{}{}",
-            termion::clear::All,
-            termion::cursor::Goto(1, 1),
-        )?;
     }
 
     Ok(())
@@ -1,231 +1,1897 @@
 use anyhow::Result;
 
-use axum::extract::State;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{FromRef, Path, Query, State};
 use axum::{
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
-use log::info;
-use std::collections::HashMap;
+use chrono::Local;
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::Instant;
+use tokio::sync::{watch, Mutex};
+use tokio::task::spawn_blocking;
+use tokio::time::{interval, Duration, Instant};
 
+use candy_game::game::accounts::{
+    hash_password, validate as validate_account, verify_password, Accounts,
+};
 use candy_game::game::api::*;
+use candy_game::game::leaderboard::Leaderboard;
+use candy_game::game::map::Map;
+use candy_game::game::maze_gen::MazeSpec;
+use candy_game::game::maze_loader::{check_capacity, maze_hash, MazeLibrary};
+use candy_game::game::mob::mob_count;
+use candy_game::game::pickup::pickup_count;
+use candy_game::game::recording::{Recording, RecordingWriter, TickRecord, RECORDING_EXTENSION};
+use candy_game::game::rules::{GameMode, LivesConfig};
+use candy_game::game::session::{hash_token, new_token};
+use candy_game::game::storage::{write_atomic, GameResult, GameStore, SavedGame};
+use candy_game::game::visibility::Sight;
 use candy_game::game::world::World;
-use candy_game::game::world_controller::run_world;
+use candy_game::game::world_controller::{
+    DEFAULT_COUNTDOWN_S, DEFAULT_LIVES, DEFAULT_RESPAWN_S, DEFAULT_TICK_MS, DEFAULT_TIME_LIMIT_S,
+    MAX_TICK_MS, MIN_TICK_MS,
+};
 
 const CLIENT_MAX_PING_S: u64 = 5;
+/// How often changed games are written to the state directory
+const SAVE_INTERVAL_S: u64 = 5;
+/// How often changed accounts and leaderboard results are written to the state directory
+const ACCOUNTS_SAVE_INTERVAL_S: u64 = 1;
+/// Finished games are archived after this long
+const FINISHED_GAME_TIMEOUT_S: u64 = 60;
+/// Games nobody plays are archived after this long
+const EMPTY_GAME_TIMEOUT_S: u64 = 120;
 
 struct Game {
     name: String,
     maze_name: String,
+    maze_hash: String,
+    maze_spec: Option<MazeSpec>,
     players: HashMap<u64, String>,
     players_last_seen: HashMap<u64, Instant>,
+    /// Player ids by their session token hash
+    sessions: HashMap<String, u64>,
+    /// Spectator ids by their session token hash, spectators aren't saved with the game
+    spectators: HashMap<String, u64>,
+    spectators_last_seen: HashMap<u64, Instant>,
+    /// Accounts of the logged in players
+    accounts: HashMap<u64, String>,
+    /// Players whose stats were added to their accounts
+    credited: HashSet<u64>,
+    tick_ms: u64,
+    countdown_ms: u64,
+    phase: GamePhase,
+    /// When the game entered its phase
+    phase_since: Instant,
+    /// Players in the lobby who are ready to start
+    ready: HashSet<u64>,
+    /// When the last player left, or the game was created without any
+    empty_since: Option<Instant>,
     world: Arc<Mutex<World>>,
+    /// Notified after every tick, also in the lobby where the world doesn't move
+    updates: watch::Sender<()>,
+    /// Set once a player asks for a rematch of the finished game
+    rematch: Option<Rematch>,
+}
+
+/// Copied out of a game so that its world can be locked after the games lock is released
+struct GameStatus {
+    phase: GamePhase,
+    starts_in_ms: Option<u64>,
+    ready: Vec<u64>,
+    tick_ms: u64,
+}
+
+/// New game with the same settings as a finished one
+struct Rematch {
+    game_id: u64,
+    /// Ids of the players who moved to the rematch by their ids in the finished game
+    players: HashMap<u64, u64>,
+}
+
+impl Game {
+    fn new(
+        name: String,
+        maze_name: String,
+        maze_hash: String,
+        maze_spec: Option<MazeSpec>,
+        world: World,
+        tick_ms: u64,
+        countdown_ms: u64,
+    ) -> Self {
+        Game {
+            name,
+            maze_name,
+            maze_hash,
+            maze_spec,
+            players: HashMap::new(),
+            players_last_seen: HashMap::new(),
+            sessions: HashMap::new(),
+            spectators: HashMap::new(),
+            spectators_last_seen: HashMap::new(),
+            accounts: HashMap::new(),
+            credited: HashSet::new(),
+            tick_ms,
+            countdown_ms,
+            phase: GamePhase::Lobby,
+            phase_since: Instant::now(),
+            ready: HashSet::new(),
+            empty_since: Some(Instant::now()),
+            world: Arc::new(Mutex::new(world.with_recording())),
+            updates: watch::channel(()).0,
+            rematch: None,
+        }
+    }
+
+    /// Takes in a player who was just spawned into the world
+    fn add_player(&mut self, player_id: u64, name: &str, account: bool) {
+        self.players.insert(player_id, name.to_string());
+        self.empty_since = None;
+        if account {
+            self.accounts.insert(player_id, name.to_string());
+        }
+    }
+
+    /// Account of the player to add their stats to, only the first time it's asked for
+    fn uncredited_account(&mut self, player_id: u64) -> Option<String> {
+        let name = self.accounts.get(&player_id)?;
+        self.credited.insert(player_id).then(|| name.clone())
+    }
+
+    /// What the state sent to viewers needs of the game besides its world
+    fn status(&self) -> GameStatus {
+        GameStatus {
+            phase: self.phase,
+            starts_in_ms: self.starts_in_ms(),
+            ready: self.ready.iter().copied().collect(),
+            tick_ms: self.tick_ms,
+        }
+    }
+
+    /// New session token for the player, their previous sessions in the game stop working
+    fn new_session(&mut self, player_id: u64) -> String {
+        let token = new_token();
+        self.sessions
+            .retain(|_, session_player| *session_player != player_id);
+        self.sessions.insert(hash_token(&token), player_id);
+        token
+    }
+
+    /// New spectator with their session token
+    fn add_spectator(&mut self) -> (u64, String) {
+        let spectator_id = rand::random();
+        let token = new_token();
+        self.spectators.insert(hash_token(&token), spectator_id);
+        self.spectators_last_seen
+            .insert(spectator_id, Instant::now());
+        (spectator_id, token)
+    }
+
+    /// Keeps the session from being removed as idle
+    fn touch(&mut self, viewer: Viewer) {
+        match viewer {
+            Viewer::Player(player_id) => self.players_last_seen.insert(player_id, Instant::now()),
+            Viewer::Spectator(spectator_id) => self
+                .spectators_last_seen
+                .insert(spectator_id, Instant::now()),
+        };
+    }
+
+    /// Player whose state the viewer gets, spectators can only follow players still in the game
+    fn viewed_player(&self, viewer: Viewer, follow: Option<u64>) -> Option<u64> {
+        match viewer {
+            Viewer::Player(player_id) => Some(player_id),
+            Viewer::Spectator(_) => follow.filter(|player_id| self.players.contains_key(player_id)),
+        }
+    }
+
+    fn join_response(&self, game_id: u64, player_id: u64, token: String) -> JoinGameResponse {
+        JoinGameResponse {
+            game_id,
+            player_id,
+            token,
+            maze_name: self.maze_name.clone(),
+            maze_hash: self.maze_hash.clone(),
+            maze_spec: self.maze_spec,
+            tick_ms: self.tick_ms,
+        }
+    }
+
+    fn spectate_response(&self, game_id: u64, token: String) -> SpectateResponse {
+        SpectateResponse {
+            game_id,
+            token,
+            maze_name: self.maze_name.clone(),
+            maze_hash: self.maze_hash.clone(),
+            maze_spec: self.maze_spec,
+            tick_ms: self.tick_ms,
+        }
+    }
+
+    fn set_phase(&mut self, phase: GamePhase) {
+        info!("Game {} is {}", self.name, phase);
+        self.phase = phase;
+        self.phase_since = Instant::now();
+    }
+
+    /// Starts the countdown once all players are ready and the game once the countdown runs out
+    fn update_lobby(&mut self) {
+        let all_ready =
+            !self.players.is_empty() && self.players.keys().all(|id| self.ready.contains(id));
+        match self.phase {
+            GamePhase::Lobby if all_ready => self.set_phase(GamePhase::Countdown),
+            GamePhase::Countdown if !all_ready => self.set_phase(GamePhase::Lobby),
+            GamePhase::Countdown if self.starts_in_ms() == Some(0) => {
+                self.set_phase(GamePhase::Running)
+            }
+            _ => {}
+        }
+    }
+
+    fn starts_in_ms(&self) -> Option<u64> {
+        (self.phase == GamePhase::Countdown).then(|| {
+            self.countdown_ms
+                .saturating_sub(self.phase_since.elapsed().as_millis() as u64)
+        })
+    }
+
+    /// The game as stored, `result` is taken from its world by `game_result`
+    fn saved(&self, game_id: u64, result: Option<GameResult>) -> SavedGame {
+        SavedGame {
+            id: game_id,
+            name: self.name.clone(),
+            maze_name: self.maze_name.clone(),
+            maze_hash: self.maze_hash.clone(),
+            maze_spec: self.maze_spec,
+            players: self.players.clone(),
+            sessions: self.sessions.clone(),
+            accounts: self.accounts.clone(),
+            credited: self.credited.clone(),
+            tick_ms: self.tick_ms,
+            countdown_ms: self.countdown_ms,
+            phase: self.phase,
+            result,
+        }
+    }
+}
+
+fn game_result(world: &World) -> Option<GameResult> {
+    world.is_finished().then(|| GameResult {
+        winners: world
+            .winners()
+            .iter()
+            .filter_map(|winner| world.player_name(*winner))
+            .map(str::to_string)
+            .collect(),
+        ticks: world.ticks_played(),
+    })
 }
 
 type SharedGames = Arc<Mutex<HashMap<u64, Game>>>;
+type SharedAccounts = Arc<Mutex<Accounts>>;
+type SharedLeaderboard = Arc<Mutex<Leaderboard>>;
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Whom a session belongs to
+#[derive(Clone, Copy)]
+enum Viewer {
+    Player(u64),
+    /// Watches the game without being in it
+    Spectator(u64),
+}
+
+impl fmt::Display for Viewer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Viewer::Player(player_id) => write!(f, "Player {}", player_id),
+            Viewer::Spectator(spectator_id) => write!(f, "Spectator {}", spectator_id),
+        }
+    }
+}
+
+/// Finds the game and the player or spectator the request's bearer token belongs to
+fn authenticate_viewer(
+    headers: &HeaderMap,
+    games: &HashMap<u64, Game>,
+) -> Result<(u64, Viewer), String> {
+    let token = bearer_token(headers).ok_or("Missing bearer session token")?;
+    let token_hash = hash_token(token);
+    games
+        .iter()
+        .find_map(|(game_id, game)| {
+            let viewer = match game.sessions.get(&token_hash) {
+                Some(player_id) => Viewer::Player(*player_id),
+                None => Viewer::Spectator(*game.spectators.get(&token_hash)?),
+            };
+            Some((*game_id, viewer))
+        })
+        .ok_or_else(|| "Invalid or expired session token".to_string())
+}
+
+/// Finds the game and player the request's bearer token belongs to, spectators are turned away
+fn authenticate(
+    headers: &HeaderMap,
+    games: &HashMap<u64, Game>,
+) -> Result<(u64, u64), (StatusCode, String)> {
+    match authenticate_viewer(headers, games) {
+        Ok((game_id, Viewer::Player(player_id))) => Ok((game_id, player_id)),
+        Ok((_, Viewer::Spectator(_))) => Err((
+            StatusCode::FORBIDDEN,
+            "Spectator sessions are read-only".to_string(),
+        )),
+        Err(msg) => Err((StatusCode::UNAUTHORIZED, msg)),
+    }
+}
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    games: SharedGames,
+    accounts: SharedAccounts,
+    leaderboard: SharedLeaderboard,
+    mazes: Arc<MazeLibrary>,
+    /// Where recordings of finished games are saved, if at all
+    record_dir: Option<PathBuf>,
+    /// Where games are kept across restarts, if at all
+    store: Option<Arc<GameStore>>,
+}
+
+async fn list_mazes(State(mazes): State<Arc<MazeLibrary>>) -> Json<GetMazesResponse> {
+    let mazes = mazes
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let map = mazes.map(&name).ok()?;
+            Some(MazeInfo {
+                name,
+                width: map.width(),
+                height: map.height(),
+            })
+        })
+        .collect();
+    Json(GetMazesResponse { mazes })
+}
+
+async fn get_maze(
+    State(mazes): State<Arc<MazeLibrary>>,
+    Path(maze_name): Path<String>,
+) -> impl IntoResponse {
+    match mazes.get(&maze_name) {
+        Some(template) => (
+            StatusCode::OK,
+            Json(GetMazeResponse {
+                name: maze_name.clone(),
+                hash: maze_hash(template),
+                template: template.to_string(),
+            }),
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("Maze {} not found", maze_name),
+        )
+            .into_response(),
+    }
+}
 
 async fn list_games(State(games): State<SharedGames>) -> Json<GetGamesResponse> {
+    let listed: Vec<_> = games
+        .lock()
+        .await
+        .iter()
+        .map(|(id, game)| {
+            let players: Vec<String> = game.players.values().cloned().collect();
+            let world = Arc::clone(&game.world);
+            (
+                *id,
+                game.name.clone(),
+                game.maze_name.clone(),
+                players,
+                game.phase,
+                world,
+            )
+        })
+        .collect();
+    // the worlds are only locked once the games lock is released
     let mut resp = GetGamesResponse { games: Vec::new() };
-    for (id, game) in games.lock().await.iter() {
+    for (id, name, maze_name, players, phase, world) in listed {
+        let world = world.lock().await;
         resp.games.push(GameInfo {
-            id: *id,
-            name: game.name.clone(),
-            maze_name: game.maze_name.clone(),
-            players: game.players.values().cloned().collect::<Vec<String>>(),
-            finished: game.world.lock().await.get_state().winner.is_some(),
+            id,
+            name,
+            maze_name,
+            players,
+            mode: world.mode(),
+            phase,
+            finished: world.is_finished(),
+            seed: world.seed(),
         });
     }
     Json(resp)
 }
 
+/// Adds the game under a new random id and starts it
+fn add_game(state: &AppState, games: &mut HashMap<u64, Game>, game: Game) -> u64 {
+    let game_id = loop {
+        let id = rand::random();
+        if !games.contains_key(&id) {
+            break id;
+        }
+    };
+    start_world(state, game_id, &game, Vec::new());
+    tokio::spawn(record_results_when_finished(
+        Arc::clone(&state.games),
+        Arc::clone(&state.accounts),
+        Arc::clone(&state.leaderboard),
+        game_id,
+        game.updates.subscribe(),
+    ));
+    games.insert(game_id, game);
+    game_id
+}
+
 async fn create_game(
-    State(games): State<SharedGames>,
+    State(state): State<AppState>,
     Json(req): Json<CreateGameRequest>,
-) -> Json<CreateGameResponse> {
-    let mut games = games.lock().await;
-    let game_id = games.len() as u64;
-    games.insert(
-        game_id,
-        Game {
-            name: req.name.clone(),
-            maze_name: req.maze_name.clone(),
-            players: HashMap::new(),
-            players_last_seen: HashMap::new(),
-            world: Arc::new(Mutex::new(World::new(
-                req.maze_name.as_str(),
-                req.mob_cnt,
-                req.candy_cnt,
-            ))),
+) -> impl IntoResponse {
+    let mazes = &state.mazes;
+    let seed = req.seed.unwrap_or_else(rand::random);
+    let maze_spec = req.generator.map(|generator| MazeSpec {
+        generator,
+        width: req.width,
+        height: req.height,
+        seed,
+    });
+    if let Some(Err(err)) = maze_spec.map(|spec| spec.check_size()) {
+        return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+    }
+    let (maze_name, maze_hash, map) = match &maze_spec {
+        Some(spec) => {
+            let template = spec.generate();
+            let map = Map::from_template(&template).expect("Generated mazes are well formed");
+            (spec.name(), maze_hash(&template), map)
+        }
+        None => match mazes.map(&req.maze_name) {
+            Ok(map) => (
+                req.maze_name.clone(),
+                maze_hash(mazes.get(&req.maze_name).expect("Maze was just loaded")),
+                map,
+            ),
+            Err(err) => return (StatusCode::NOT_FOUND, err.to_string()).into_response(),
         },
+    };
+    let mob_configs = req.mob_configs();
+    if let Err(err) = check_capacity(
+        &map,
+        mob_count(&mob_configs),
+        req.candy_cnt,
+        pickup_count(&req.pickups),
+    ) {
+        return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+    }
+
+    if req.time_limit_s == Some(0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Games need a time limit of at least 1 second",
+        )
+            .into_response();
+    }
+    if req.lives == Some(0) {
+        return (StatusCode::BAD_REQUEST, "Players need at least one life").into_response();
+    }
+    if req
+        .visibility
+        .is_some_and(|visibility| !visibility.is_limited())
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Fog of war needs a sight radius or line of sight",
+        )
+            .into_response();
+    }
+
+    let tick_ms = req.tick_ms.unwrap_or(DEFAULT_TICK_MS);
+    if !(MIN_TICK_MS..=MAX_TICK_MS).contains(&tick_ms) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Ticks have to last {} to {} milliseconds",
+                MIN_TICK_MS, MAX_TICK_MS
+            ),
+        )
+            .into_response();
+    }
+    let lives = LivesConfig::new(
+        req.lives.unwrap_or(DEFAULT_LIVES),
+        req.respawn_s
+            .unwrap_or(DEFAULT_RESPAWN_S)
+            .saturating_mul(1000),
+        tick_ms,
     );
-    run_world(Arc::clone(&games.get(&game_id).unwrap().world));
-    info!("Game {} created using {:?}", game_id, req);
-    Json(CreateGameResponse { game_id })
+    // only deathmatches have an outcome once time is up
+    let time_limit = (req.mode == GameMode::Deathmatch).then(|| {
+        req.time_limit_s
+            .unwrap_or(DEFAULT_TIME_LIMIT_S)
+            .saturating_mul(1000)
+            .div_ceil(tick_ms)
+    });
+    let game = Game::new(
+        req.name.clone(),
+        maze_name,
+        maze_hash,
+        maze_spec,
+        World::with_seed(
+            map,
+            &mob_configs,
+            req.candy_cnt,
+            &req.pickups,
+            req.mode,
+            lives,
+            seed,
+        )
+        .with_visibility(req.visibility)
+        .with_time_limit(time_limit)
+        .with_tick_ms(tick_ms),
+        tick_ms,
+        req.countdown_s
+            .unwrap_or(DEFAULT_COUNTDOWN_S)
+            .saturating_mul(1000),
+    );
+    let game_id = add_game(&state, &mut *state.games.lock().await, game);
+    info!(
+        "Game {} created with seed {} using {:?}",
+        game_id, seed, req
+    );
+    (StatusCode::OK, Json(CreateGameResponse { game_id })).into_response()
+}
+
+/// Starts ticking and recording the game, `ticks` were played before the server restarted
+fn start_world(state: &AppState, game_id: u64, game: &Game, ticks: Vec<TickRecord>) {
+    tokio::spawn(run_game(Arc::clone(&state.games), game_id, game.tick_ms));
+    // finished games already have their whole recording stored
+    if game.phase != GamePhase::Finished {
+        tokio::spawn(record_game(
+            Arc::clone(&game.world),
+            game.updates.subscribe(),
+            game_id,
+            game.tick_ms,
+            ticks,
+            state.store.clone(),
+            state.record_dir.clone(),
+        ));
+    }
+}
+
+/// Ticks the game until it's finished or archived. The world only moves while the game is
+/// running, in the lobby and during the countdown the ticks just let players see who is ready.
+async fn run_game(games: SharedGames, game_id: u64, tick_ms: u64) {
+    let mut ticker = interval(Duration::from_millis(tick_ms));
+    loop {
+        ticker.tick().await;
+        let world = {
+            let mut games = games.lock().await;
+            let Some(game) = games.get_mut(&game_id) else {
+                return;
+            };
+            game.update_lobby();
+            if game.phase != GamePhase::Running {
+                let _ = game.updates.send(());
+                if game.phase == GamePhase::Finished {
+                    return;
+                }
+                continue;
+            }
+            Arc::clone(&game.world)
+        };
+        // only the game's own world is locked while it moves, not all the games
+        let finished = {
+            let mut world = world.lock().await;
+            world.move_world();
+            world.is_finished()
+        };
+        let mut games = games.lock().await;
+        let Some(game) = games.get_mut(&game_id) else {
+            return;
+        };
+        if finished {
+            game.set_phase(GamePhase::Finished);
+        }
+        let _ = game.updates.send(());
+        if finished {
+            return;
+        }
+    }
+}
+
+/// Writes the ticks of the game to its recording in the store as they're played and, with
+/// `record_dir`, to a file there that is kept once the game is finished
+async fn record_game(
+    world: Arc<Mutex<World>>,
+    mut ticks: watch::Receiver<()>,
+    game_id: u64,
+    tick_ms: u64,
+    past_ticks: Vec<TickRecord>,
+    store: Option<Arc<GameStore>>,
+    record_dir: Option<PathBuf>,
+) {
+    let recording = {
+        let mut world = world.lock().await;
+        let mut past_ticks = past_ticks;
+        past_ticks.extend(world.take_recorded_ticks());
+        Recording {
+            tick_ms,
+            header: world.recording_header().clone(),
+            ticks: past_ticks,
+        }
+    };
+    let mut writers = Vec::new();
+    if let Some(store) = &store {
+        match store.start_recording(game_id, &recording) {
+            Ok(writer) => writers.push(writer),
+            Err(err) => error!("Couldn't store recording of game {}: {:?}", game_id, err),
+        }
+    }
+    // in progress recordings aren't listed, a restarted game starts its file over
+    let part_path = record_dir
+        .as_ref()
+        .map(|dir| dir.join(format!("{}.{}.part", game_id, RECORDING_EXTENSION)));
+    if let Some(path) = &part_path {
+        match RecordingWriter::create(path, &recording) {
+            Ok(writer) => writers.push(writer),
+            Err(err) => error!("Couldn't save recording: {:?}", err),
+        }
+    }
+    drop(recording);
+
+    let mut finished = false;
+    while !finished {
+        // the game is gone once it's archived, its last ticks are still written
+        let archived = ticks.changed().await.is_err();
+        let recorded = {
+            let mut world = world.lock().await;
+            finished = world.is_finished();
+            world.take_recorded_ticks()
+        };
+        for writer in &mut writers {
+            if let Err(err) = writer.write(&recorded) {
+                error!("Couldn't record game {}: {:?}", game_id, err);
+            }
+        }
+        if archived {
+            break;
+        }
+    }
+    drop(writers);
+
+    let (Some(part_path), Some(dir)) = (part_path, record_dir) else {
+        return;
+    };
+    if !finished {
+        let _ = std::fs::remove_file(part_path);
+        return;
+    }
+    let path = dir.join(format!(
+        "{}-{}.{}",
+        game_id,
+        Local::now().format("%Y%m%d-%H%M%S"),
+        RECORDING_EXTENSION
+    ));
+    match std::fs::rename(part_path, &path) {
+        Ok(()) => info!("Recording saved to {:?}", path),
+        Err(err) => error!("Couldn't save recording: {:?}", err),
+    }
+}
+
+async fn list_recordings(State(record_dir): State<Option<PathBuf>>) -> Json<GetRecordingsResponse> {
+    let mut recordings = Vec::new();
+    if let Some(Ok(entries)) = record_dir.map(std::fs::read_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == RECORDING_EXTENSION)
+            {
+                recordings.push(RecordingInfo {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    size: entry.metadata().map(|meta| meta.len()).unwrap_or(0),
+                });
+            }
+        }
+    }
+    recordings.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(GetRecordingsResponse { recordings })
+}
+
+async fn get_recording(
+    State(record_dir): State<Option<PathBuf>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    let file = record_dir
+        .filter(|_| !name.contains(['/', '\\']) && name.ends_with(RECORDING_EXTENSION))
+        .and_then(|dir| std::fs::read(dir.join(&name)).ok());
+    match file {
+        Some(bytes) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            bytes,
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("Recording {} not found", name),
+        )
+            .into_response(),
+    }
+}
+
+async fn register(
+    State(accounts): State<SharedAccounts>,
+    Json(req): Json<AccountRequest>,
+) -> impl IntoResponse {
+    if accounts.lock().await.is_registered(&req.name) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Name {} is already taken", req.name),
+        )
+            .into_response();
+    }
+    if let Err(err) = validate_account(&req.name, &req.password) {
+        return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+    }
+    let password = req.password.clone();
+    let hashed = spawn_blocking(move || hash_password(&password)).await;
+    let password_hash = match hashed.map_err(anyhow::Error::from).and_then(|hash| hash) {
+        Ok(password_hash) => password_hash,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    match accounts.lock().await.register(&req.name, password_hash) {
+        Ok(()) => {
+            info!("Player {} registered", req.name);
+            (StatusCode::OK, "OK").into_response()
+        }
+        Err(err) => (StatusCode::CONFLICT, err.to_string()).into_response(),
+    }
+}
+
+async fn login(
+    State(accounts): State<SharedAccounts>,
+    Json(req): Json<AccountRequest>,
+) -> impl IntoResponse {
+    let password_hash = accounts.lock().await.password_hash(&req.name);
+    let verified = match password_hash {
+        Some(password_hash) => {
+            let password = req.password.clone();
+            spawn_blocking(move || verify_password(&password, &password_hash))
+                .await
+                .unwrap_or(false)
+        }
+        None => false,
+    };
+    if !verified {
+        return (
+            StatusCode::UNAUTHORIZED,
+            "Wrong name or password".to_string(),
+        )
+            .into_response();
+    }
+    let token = accounts.lock().await.login(&req.name);
+    (StatusCode::OK, Json(LoginResponse { token })).into_response()
+}
+
+async fn get_player(
+    State(accounts): State<SharedAccounts>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    match accounts.lock().await.profile(&name) {
+        Some(profile) => (StatusCode::OK, Json(profile)).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("Player {} not found", name)).into_response(),
+    }
+}
+
+async fn get_leaderboard(
+    State(leaderboard): State<SharedLeaderboard>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Json<LeaderboardResponse> {
+    Json(leaderboard.lock().await.best(query.maze.as_deref()))
+}
+
+/// Adds the player's stats from a game to their account
+async fn credit_account(accounts: &SharedAccounts, name: &str, stats: &PlayerStats) {
+    if let Err(err) = accounts.lock().await.add_stats(name, stats) {
+        error!("Couldn't update stats of {}: {:?}", name, err);
+    }
+}
+
+/// Adds the scores of the remaining players to the leaderboard and their stats to their accounts
+/// once the game is finished
+async fn record_results_when_finished(
+    games: SharedGames,
+    accounts: SharedAccounts,
+    leaderboard: SharedLeaderboard,
+    game_id: u64,
+    mut ticks: watch::Receiver<()>,
+) {
+    while ticks.changed().await.is_ok() {
+        let (world, account_players) = match games.lock().await.get(&game_id) {
+            Some(game) => {
+                let account_players: Vec<u64> = game.accounts.keys().copied().collect();
+                (Arc::clone(&game.world), account_players)
+            }
+            None => return,
+        };
+        let (scores, stats) = {
+            let world = world.lock().await;
+            if !world.is_finished() {
+                continue;
+            }
+            let stats: Vec<(u64, PlayerStats)> = account_players
+                .into_iter()
+                .map(|player_id| (player_id, world.player_stats(player_id)))
+                .collect();
+            (world.scores(), stats)
+        };
+        let (maze_name, tick_ms, credited) = {
+            let mut games = games.lock().await;
+            let Some(game) = games.get_mut(&game_id) else {
+                return;
+            };
+            let credited: Vec<(String, PlayerStats)> = stats
+                .into_iter()
+                .filter_map(|(player_id, stats)| Some((game.uncredited_account(player_id)?, stats)))
+                .collect();
+            (game.maze_name.clone(), game.tick_ms, credited)
+        };
+        leaderboard
+            .lock()
+            .await
+            .record_game(game_id, &maze_name, tick_ms, &scores);
+        for (name, stats) in credited {
+            credit_account(&accounts, &name, &stats).await;
+        }
+        return;
+    }
 }
 
 async fn join_game(
     State(games): State<SharedGames>,
+    State(accounts): State<SharedAccounts>,
+    headers: HeaderMap,
     Json(req): Json<JoinGameRequest>,
 ) -> impl IntoResponse {
-    let mut games = games.lock().await;
-    if let Some(game) = games.get_mut(&req.game_id) {
-        if game.players.values().any(|val| val == &req.player_name) {
+    // logged in players play under their account name, guests can't take registered names
+    let (player_name, account) = {
+        let accounts = accounts.lock().await;
+        match bearer_token(&headers) {
+            Some(token) => match accounts.authenticate(token) {
+                Some(name) => (name.to_string(), true),
+                None => {
+                    return (
+                        StatusCode::UNAUTHORIZED,
+                        "Invalid or expired login token".to_string(),
+                    )
+                        .into_response()
+                }
+            },
+            None if accounts.is_registered(&req.player_name) => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "Name {} belongs to a registered player, log in to use it",
+                        req.player_name
+                    ),
+                )
+                    .into_response()
+            }
+            None => (req.player_name.clone(), false),
+        }
+    };
+
+    let world = match games.lock().await.get(&req.game_id) {
+        Some(game) if game.phase == GamePhase::Finished => {
             return (
-                StatusCode::BAD_REQUEST,
-                format!("Player {} already in game {}", req.player_name, req.game_id),
+                StatusCode::CONFLICT,
+                format!("Game {} already finished", req.game_id),
             )
-                .into_response();
+                .into_response()
         }
-        let mut world = game.world.lock().await;
-        let player_id = world.spawn_player(req.player_name.as_str());
-        game.players.insert(player_id, req.player_name.clone());
-        info!(
-            "Player {} with id {} joined game {}",
-            req.player_name, player_id, req.game_id
-        );
+        Some(game) => Arc::clone(&game.world),
+        None => return game_not_found(req.game_id),
+    };
+    let player_id = match spawn_player(&world, req.game_id, &player_name).await {
+        Ok(player_id) => player_id,
+        Err(err) => return err.into_response(),
+    };
+
+    let mut games = games.lock().await;
+    // archived while the player was placed
+    let Some(game) = games.get_mut(&req.game_id) else {
+        return game_not_found(req.game_id);
+    };
+    game.add_player(player_id, &player_name, account);
+    let token = game.new_session(player_id);
+    info!(
+        "Player {} with id {} joined game {}",
+        player_name, player_id, req.game_id
+    );
+    (
+        StatusCode::OK,
+        Json(game.join_response(req.game_id, player_id, token)),
+    )
+        .into_response()
+}
+
+fn game_not_found(game_id: u64) -> Response {
+    (StatusCode::NOT_FOUND, format!("Game {} not found", game_id)).into_response()
+}
+
+/// Places the player in the world locking only the world, so that the other games go on
+/// meanwhile. The name is checked under the same lock, so that nobody joins twice.
+async fn spawn_player(
+    world: &Mutex<World>,
+    game_id: u64,
+    name: &str,
+) -> Result<u64, (StatusCode, String)> {
+    let mut world = world.lock().await;
+    if world.has_player_named(name) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Player {} already in game {}", name, game_id),
+        ));
+    }
+    world.spawn_player(name).ok_or_else(|| {
         (
-            StatusCode::OK,
-            Json(JoinGameResponse {
-                player_id,
-                maze_name: game.maze_name.clone(),
-            }),
+            StatusCode::CONFLICT,
+            format!("Game {} has no room left", game_id),
         )
-            .into_response()
-    } else {
-        (
+    })
+}
+
+async fn spectate(
+    State(games): State<SharedGames>,
+    Json(req): Json<SpectateRequest>,
+) -> impl IntoResponse {
+    let mut games = games.lock().await;
+    let Some(game) = games.get_mut(&req.game_id) else {
+        return (
             StatusCode::NOT_FOUND,
             format!("Game {} not found", req.game_id),
         )
-            .into_response()
+            .into_response();
+    };
+    let (spectator_id, token) = game.add_spectator();
+    info!("Spectator {} watches game {}", spectator_id, req.game_id);
+    (
+        StatusCode::OK,
+        Json(game.spectate_response(req.game_id, token)),
+    )
+        .into_response()
+}
+
+/// Moves the player of a finished game to its rematch, creating the rematch if needed
+async fn rematch(
+    State(state): State<AppState>,
+    Path(game_id): Path<u64>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let (player_id, finished_world) = {
+        let games = state.games.lock().await;
+        let player_id = match authenticate(&headers, &games) {
+            Ok((session_game_id, player_id)) if session_game_id == game_id => player_id,
+            Ok(_) => {
+                return (
+                    StatusCode::FORBIDDEN,
+                    format!("Not a player of game {}", game_id),
+                )
+                    .into_response()
+            }
+            Err(err) => return err.into_response(),
+        };
+        let game = &games[&game_id];
+        if game.phase != GamePhase::Finished {
+            return (
+                StatusCode::CONFLICT,
+                format!("Game {} isn't finished yet", game_id),
+            )
+                .into_response();
+        }
+        (player_id, Arc::clone(&game.world))
+    };
+    // built before it's known whether somebody else opened a rematch meanwhile, so that the
+    // games lock isn't held while the finished world is locked
+    let rematch_world = finished_world.lock().await.rematch(rand::random());
+
+    let (player_name, account, rematch_id, moved_player, rematch_world) = {
+        let mut games = state.games.lock().await;
+        let Some(game) = games.get(&game_id) else {
+            return game_not_found(game_id);
+        };
+        // a rematch that finished as well is replaced by a new one
+        let rematch_open = game.rematch.as_ref().is_some_and(|rematch| {
+            games
+                .get(&rematch.game_id)
+                .is_some_and(|rematch_game| rematch_game.phase != GamePhase::Finished)
+        });
+        if !rematch_open {
+            let rematch_game = Game::new(
+                game.name.clone(),
+                game.maze_name.clone(),
+                game.maze_hash.clone(),
+                game.maze_spec,
+                rematch_world,
+                game.tick_ms,
+                game.countdown_ms,
+            );
+            let rematch_id = add_game(&state, &mut games, rematch_game);
+            info!(
+                "Game {} created as a rematch of game {}",
+                rematch_id, game_id
+            );
+            games
+                .get_mut(&game_id)
+                .expect("Game was just found")
+                .rematch = Some(Rematch {
+                game_id: rematch_id,
+                players: HashMap::new(),
+            });
+        }
+
+        let game = &games[&game_id];
+        let rematch = game.rematch.as_ref().expect("Rematch was just created");
+        let rematch_game = &games[&rematch.game_id];
+        let moved_player = rematch
+            .players
+            .get(&player_id)
+            .copied()
+            // removed from the rematch for being idle, if not found
+            .filter(|id| rematch_game.players.contains_key(id));
+        (
+            game.players[&player_id].clone(),
+            game.accounts.contains_key(&player_id),
+            rematch.game_id,
+            moved_player,
+            Arc::clone(&rematch_game.world),
+        )
+    };
+
+    let rematch_player = match moved_player {
+        Some(id) => id,
+        None => {
+            let id = match spawn_player(&rematch_world, rematch_id, &player_name).await {
+                Ok(id) => id,
+                Err(err) => return err.into_response(),
+            };
+            info!(
+                "Player {} with id {} joined rematch game {}",
+                player_name, id, rematch_id
+            );
+            id
+        }
+    };
+
+    let mut games = state.games.lock().await;
+    let Some(rematch_game) = games.get_mut(&rematch_id) else {
+        return game_not_found(rematch_id);
+    };
+    if moved_player.is_none() {
+        rematch_game.add_player(rematch_player, &player_name, account);
+    }
+    let token = rematch_game.new_session(rematch_player);
+    let resp = rematch_game.join_response(rematch_id, rematch_player, token);
+    if let Some(rematch) = games
+        .get_mut(&game_id)
+        .and_then(|game| game.rematch.as_mut())
+    {
+        rematch.players.insert(player_id, rematch_player);
+    }
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+/// State of the game as seen by the player, spectators get it without any player
+fn player_state(
+    game: &GameStatus,
+    world: &World,
+    player_id: Option<u64>,
+    since_tick: Option<u64>,
+) -> GetStateResponse {
+    let state = world.get_state_since(since_tick, player_id);
+    GetStateResponse {
+        player_id,
+        phase: game.phase,
+        starts_in_ms: game.starts_in_ms,
+        ready: game.ready.clone(),
+        tick: state.tick,
+        objects: state.objects,
+        is_finished: world.is_finished(),
+        player_winner: player_id.is_some_and(|id| state.winners.contains(&id)),
+        player_dead: player_id.is_some_and(|id| state.dead_players.contains(&id)),
+        lives: player_id.map_or(0, |id| world.lives(id)),
+        respawn_in_ms: player_id
+            .and_then(|id| world.respawn_in(id))
+            .map(|ticks| ticks * game.tick_ms),
+        effects: player_id
+            .map(|id| world.effects(id))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(kind, ticks)| ActiveEffect {
+                kind,
+                remaining_ms: ticks * game.tick_ms,
+            })
+            .collect(),
+        keys: player_id.map_or(0, |id| world.keys(id)),
+        sight: player_id.map_or(Sight::Everything, |id| world.sight(id)),
+        events: state.events.to_vec(),
+        scores: world.scores(),
     }
 }
 
 async fn game_state(
     State(games): State<SharedGames>,
+    headers: HeaderMap,
     Json(req): Json<GetStateRequest>,
 ) -> impl IntoResponse {
-    if let Some(game) = games.lock().await.get_mut(&req.game_id) {
-        game.players_last_seen.insert(req.player_id, Instant::now());
-
-        let world = game.world.lock().await;
-        let state = world.get_state();
-        let resp = GetStateResponse {
-            objects: state.objects,
-            is_finished: state.winner.is_some(),
-            player_winner: state.winner.is_some_and(|winner| winner == req.player_id),
-            player_dead: state.dead_players.contains(&req.player_id),
-            logs: state.logs.clone(),
-        };
-        (StatusCode::OK, Json(resp)).into_response()
-    } else {
-        (
-            StatusCode::NOT_FOUND,
-            format!("Game {} not found", req.game_id),
+    let (status, world, player_id) = {
+        let mut games = games.lock().await;
+        let (game_id, viewer) = match authenticate_viewer(&headers, &games) {
+            Ok(session) => session,
+            Err(msg) => return (StatusCode::UNAUTHORIZED, msg).into_response(),
+        };
+        let game = games
+            .get_mut(&game_id)
+            .expect("Sessions belong to existing games");
+        game.touch(viewer);
+        let player_id = game.viewed_player(viewer, req.follow);
+        (game.status(), Arc::clone(&game.world), player_id)
+    };
+    let resp = player_state(&status, &*world.lock().await, player_id, req.since_tick);
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+async fn player_ready(State(games): State<SharedGames>, headers: HeaderMap) -> impl IntoResponse {
+    let mut games = games.lock().await;
+    let (game_id, player_id) = match authenticate(&headers, &games) {
+        Ok(session) => session,
+        Err(err) => return err.into_response(),
+    };
+    let game = games
+        .get_mut(&game_id)
+        .expect("Sessions belong to existing games");
+    match game.phase {
+        GamePhase::Lobby | GamePhase::Countdown => {
+            game.ready.insert(player_id);
+            (StatusCode::OK, "OK").into_response()
+        }
+        _ => (
+            StatusCode::CONFLICT,
+            format!("Game {} already started", game_id),
         )
-            .into_response()
+            .into_response(),
+    }
+}
+
+fn apply_action(
+    game_id: u64,
+    phase: GamePhase,
+    world: &mut World,
+    player_id: u64,
+    action: PlayerAction,
+) -> Result<(), String> {
+    if phase != GamePhase::Running {
+        return Err(format!("Game {} isn't running", game_id));
+    }
+    let state = world.get_state();
+    if world.is_finished() {
+        return Err(format!("Game {} already finished", game_id));
+    }
+    if state.dead_players.contains(&player_id) {
+        return Err(format!("Player {} already dead", player_id));
+    }
+    if world.player_name(player_id).is_none() {
+        return Err(format!("Player {} not in game {}", player_id, game_id));
     }
+    world.queue_action(player_id, action);
+    Ok(())
 }
 
 async fn do_action(
     State(games): State<SharedGames>,
+    headers: HeaderMap,
     Json(req): Json<ActionRequest>,
 ) -> impl IntoResponse {
-    if let Some(game) = games.lock().await.get_mut(&req.game_id) {
-        let mut world = game.world.lock().await;
-        let state = world.get_state();
-        if state.winner.is_some() {
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Game {} already finished", req.game_id),
-            )
-                .into_response();
+    let (game_id, player_id, phase, world) = {
+        let games = games.lock().await;
+        let (game_id, player_id) = match authenticate(&headers, &games) {
+            Ok(session) => session,
+            Err(err) => return err.into_response(),
+        };
+        let game = &games[&game_id];
+        (game_id, player_id, game.phase, Arc::clone(&game.world))
+    };
+    let mut world = world.lock().await;
+    match apply_action(game_id, phase, &mut world, player_id, req.action) {
+        Ok(()) => (StatusCode::OK, "OK").into_response(),
+        Err(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+    }
+}
+
+async fn game_socket(
+    ws: WebSocketUpgrade,
+    State(games): State<SharedGames>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let (game_id, viewer, ticks) = {
+        let games = games.lock().await;
+        match authenticate_viewer(&headers, &games) {
+            Ok((game_id, viewer)) => (game_id, viewer, games[&game_id].updates.subscribe()),
+            Err(msg) => return (StatusCode::UNAUTHORIZED, msg).into_response(),
         }
-        if state.dead_players.contains(&req.player_id) {
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Player {} already dead", req.player_id),
-            )
-                .into_response();
+    };
+    ws.on_upgrade(move |socket| handle_socket(socket, games, game_id, viewer, ticks))
+}
+
+/// Pushes the viewer's state after every world tick and applies actions, or for spectators
+/// changes of the followed player, received over the same socket until either side disconnects
+async fn handle_socket(
+    socket: WebSocket,
+    games: SharedGames,
+    game_id: u64,
+    viewer: Viewer,
+    mut ticks: watch::Receiver<()>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut last_tick = None;
+    let mut follow = None;
+    loop {
+        tokio::select! {
+            tick = ticks.changed() => {
+                if tick.is_err() {
+                    break;
+                }
+                let (status, world, player_id) = match games.lock().await.get_mut(&game_id) {
+                    Some(game) => {
+                        game.touch(viewer);
+                        let player_id = game.viewed_player(viewer, follow);
+                        (game.status(), Arc::clone(&game.world), player_id)
+                    }
+                    None => break,
+                };
+                let resp = player_state(&status, &*world.lock().await, player_id, last_tick);
+                last_tick = Some(resp.tick);
+                let frame = serde_json::to_string(&resp).expect("State is serializable");
+                if sender.send(Message::Text(frame)).await.is_err() {
+                    break;
+                }
+            }
+            msg = receiver.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let Viewer::Player(player_id) = viewer else {
+                    match serde_json::from_str::<FollowRequest>(&text) {
+                        Ok(req) => {
+                            follow = req.player_id;
+                            // the spectator sees what the followed player sees from now on
+                            last_tick = None;
+                        }
+                        Err(_) => info!("{} sent malformed follow request", viewer),
+                    }
+                    continue;
+                };
+                let Ok(action) = serde_json::from_str::<PlayerAction>(&text) else {
+                    info!("{} sent malformed action", viewer);
+                    continue;
+                };
+                let game = games
+                    .lock()
+                    .await
+                    .get(&game_id)
+                    .map(|game| (game.phase, Arc::clone(&game.world)));
+                if let Some((phase, world)) = game {
+                    let _ = apply_action(game_id, phase, &mut *world.lock().await, player_id, action);
+                }
+            }
         }
-        if !game.players.contains_key(&req.player_id) {
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Player {} not in game {}", req.player_id, req.game_id),
-            )
-                .into_response();
+    }
+    info!("{} disconnected from game {} socket", viewer, game_id);
+}
+
+async fn clean_idle_players(games: SharedGames, accounts: SharedAccounts) {
+    let mut interval = interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        remove_idle_players(&games, &accounts).await;
+        accounts.lock().await.remove_expired_logins();
+    }
+}
+
+/// Removes players and spectators who didn't show up for a while. Players of finished games are
+/// kept, they may still ask for a rematch until the game is archived.
+async fn remove_idle_players(games: &SharedGames, accounts: &SharedAccounts) {
+    // (world, player, account to credit) of every removed player, the worlds are only locked
+    // once the games lock is released
+    let mut removed = Vec::new();
+    for game in games.lock().await.values_mut() {
+        game.spectators_last_seen
+            .retain(|_, last_seen| last_seen.elapsed().as_secs() <= CLIENT_MAX_PING_S);
+        let spectators_last_seen = &game.spectators_last_seen;
+        game.spectators
+            .retain(|_, spectator_id| spectators_last_seen.contains_key(spectator_id));
+
+        if game.phase == GamePhase::Finished {
+            continue;
         }
-        match req.action {
-            PlayerAction::Shoot => world.player_shoot(req.player_id),
-            PlayerAction::Move(dir) => world.move_player(req.player_id, dir),
+        let idle_players: Vec<u64> = game
+            .players_last_seen
+            .iter()
+            .filter(|(_, last_seen)| last_seen.elapsed().as_secs() > CLIENT_MAX_PING_S)
+            .map(|(id, _)| *id)
+            .collect();
+        for player_id in idle_players {
+            info!(
+                "Player {} ({}) removed from game {}",
+                player_id, game.players[&player_id], game.name
+            );
+            let account = game.uncredited_account(player_id);
+            removed.push((Arc::clone(&game.world), player_id, account));
+            game.players_last_seen.remove(&player_id);
+            game.players.remove(&player_id);
+            game.ready.remove(&player_id);
+            game.sessions
+                .retain(|_, session_player| *session_player != player_id);
+            if game.players.is_empty() {
+                game.empty_since = Some(Instant::now());
+            }
+        }
+    }
+
+    for (world, player_id, account) in removed {
+        let stats = {
+            let mut world = world.lock().await;
+            let stats = world.player_stats(player_id);
+            world.erase_player(player_id);
+            stats
+        };
+        if let Some(name) = account {
+            credit_account(accounts, &name, &stats).await;
         }
-        (StatusCode::OK, "OK").into_response()
-    } else {
-        (
-            StatusCode::NOT_FOUND,
-            format!("Game {} not found", req.game_id),
-        )
-            .into_response()
     }
 }
 
-async fn clean_idle_players(games: SharedGames) {
+/// Removes games that finished or that nobody plays for a while, archiving them in the store
+async fn archive_games(games: SharedGames, store: Option<Arc<GameStore>>) {
+    let mut interval = interval(Duration::from_secs(1));
     loop {
-        let mut games = games.lock().await;
-        for game in games.values_mut() {
-            let idle_players: Vec<u64> = game
-                .players_last_seen
+        interval.tick().await;
+        let expired: Vec<(u64, Game)> = {
+            let mut games = games.lock().await;
+            let expired: Vec<u64> = games
                 .iter()
-                .filter_map(|(id, last_seen)| {
-                    if last_seen.elapsed().as_secs() > CLIENT_MAX_PING_S {
-                        Some(*id)
-                    } else {
-                        None
+                .filter(|(_, game)| match game.phase {
+                    GamePhase::Finished => {
+                        game.phase_since.elapsed().as_secs() >= FINISHED_GAME_TIMEOUT_S
                     }
+                    _ => game
+                        .empty_since
+                        .is_some_and(|since| since.elapsed().as_secs() >= EMPTY_GAME_TIMEOUT_S),
                 })
+                .map(|(id, _)| *id)
                 .collect();
+            expired
+                .into_iter()
+                .map(|game_id| {
+                    (
+                        game_id,
+                        games.remove(&game_id).expect("Game was just found"),
+                    )
+                })
+                .collect()
+        };
+        // the games are gone from the map, so their worlds are locked without the games lock
+        let mut archived = Vec::new();
+        for (game_id, mut game) in expired {
+            game.set_phase(GamePhase::Archived);
+            let result = game_result(&*game.world.lock().await);
+            archived.push(game.saved(game_id, result));
+        }
+        let Some(store) = &store else {
+            continue;
+        };
+        for saved in archived {
+            if let Err(err) = store.archive(&saved) {
+                error!("Couldn't archive game {}: {:?}", saved.id, err);
+            }
+        }
+    }
+}
 
-            for player_id in idle_players {
-                info!(
-                    "Player {} ({}) removed from game {}",
-                    player_id, game.players[&player_id], game.name
-                );
-                game.world.lock().await.erase_player(player_id);
-                game.players_last_seen.remove(&player_id);
-                game.players.remove(&player_id);
+/// Rebuilds the stored games by replaying their recordings, each with the ticks played so far
+fn restore_games(store: &GameStore) -> Result<Vec<(u64, Game, Vec<TickRecord>)>> {
+    let mut games = Vec::new();
+    for (saved, recording) in store.load_all()? {
+        let mut world = match recording.world_at(recording.ticks.len()) {
+            Ok(world) => world.with_recording(),
+            Err(err) => {
+                error!("Couldn't replay game {}: {:?}", saved.id, err);
+                continue;
+            }
+        };
+        // players who joined after the game was last saved have no session to come back with
+        for score in world.scores() {
+            if !world.is_finished() && !saved.players.contains_key(&score.player_id) {
+                world.erase_player(score.player_id);
             }
         }
+        // players who joined after the last recorded tick never made it into the recording
+        let players: HashMap<u64, String> = saved
+            .players
+            .into_iter()
+            .filter(|(id, _)| world.player_name(*id).is_some())
+            .collect();
+        // restored players get the usual time to reconnect before they're removed
+        let players_last_seen = players.keys().map(|id| (*id, Instant::now())).collect();
+        let players_empty_since = players.is_empty().then(Instant::now);
+        let sessions = saved
+            .sessions
+            .into_iter()
+            .filter(|(_, player_id)| players.contains_key(player_id))
+            .collect();
+        let accounts = saved
+            .accounts
+            .into_iter()
+            .filter(|(player_id, _)| players.contains_key(player_id))
+            .collect();
+        // readiness isn't stored, so an interrupted countdown waits for the players again
+        let phase = match saved.phase {
+            GamePhase::Countdown => GamePhase::Lobby,
+            phase => phase,
+        };
+        info!(
+            "Game {} ({}) restored with {} players",
+            saved.id,
+            saved.name,
+            players.len()
+        );
+        games.push((
+            saved.id,
+            Game {
+                name: saved.name,
+                maze_name: saved.maze_name,
+                maze_hash: saved.maze_hash,
+                maze_spec: saved.maze_spec,
+                players,
+                players_last_seen,
+                sessions,
+                spectators: HashMap::new(),
+                spectators_last_seen: HashMap::new(),
+                accounts,
+                credited: saved.credited,
+                tick_ms: saved.tick_ms,
+                countdown_ms: saved.countdown_ms,
+                phase,
+                phase_since: Instant::now(),
+                ready: HashSet::new(),
+                empty_since: players_empty_since,
+                world: Arc::new(Mutex::new(world)),
+                updates: watch::channel(()).0,
+                rematch: None,
+            },
+            recording.ticks,
+        ));
+    }
+    Ok(games)
+}
+
+/// Writes what `unsaved` of the accounts or the leaderboard returned
+fn write_unsaved(what: &str, unsaved: Result<Option<(PathBuf, Vec<u8>)>>) {
+    let result = match unsaved {
+        Ok(Some((path, bytes))) => write_atomic(&path, &bytes),
+        Ok(None) => Ok(()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        error!("Couldn't save {}: {:?}", what, err);
     }
 }
 
+/// Writes the accounts and the leaderboard to their files if they changed, they're only locked
+/// to serialize them
+async fn save_accounts(accounts: &SharedAccounts, leaderboard: &SharedLeaderboard) {
+    let unsaved = accounts.lock().await.unsaved();
+    write_unsaved("accounts", unsaved);
+    let unsaved = leaderboard.lock().await.unsaved();
+    write_unsaved("leaderboard", unsaved);
+}
+
+async fn persist_accounts(accounts: SharedAccounts, leaderboard: SharedLeaderboard) {
+    let mut interval = tokio::time::interval(Duration::from_secs(ACCOUNTS_SAVE_INTERVAL_S));
+    loop {
+        interval.tick().await;
+        save_accounts(&accounts, &leaderboard).await;
+    }
+}
+
+/// Writes games that changed since `saved_ticks` to the store. Every world is locked on its own
+/// to find out whether it changed, the games only to copy their metadata.
+async fn save_games(games: &SharedGames, store: &GameStore, saved_ticks: &mut HashMap<u64, u64>) {
+    let worlds: Vec<(u64, Arc<Mutex<World>>)> = games
+        .lock()
+        .await
+        .iter()
+        .map(|(id, game)| (*id, Arc::clone(&game.world)))
+        .collect();
+    saved_ticks.retain(|id, _| worlds.iter().any(|(game_id, _)| game_id == id));
+    for (id, world) in worlds {
+        let (tick, result) = {
+            let world = world.lock().await;
+            (world.tick(), game_result(&world))
+        };
+        if saved_ticks.get(&id) == Some(&tick) {
+            continue;
+        }
+        let saved = match games.lock().await.get(&id) {
+            Some(game) => game.saved(id, result),
+            // archived meanwhile, which saves it one last time
+            None => continue,
+        };
+        saved_ticks.insert(id, tick);
+        if let Err(err) = store.save(&saved) {
+            error!("Couldn't save game {}: {:?}", saved.id, err);
+        }
+    }
+}
+
+async fn persist_games(games: SharedGames, store: Arc<GameStore>) {
+    let mut saved_ticks = HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(SAVE_INTERVAL_S));
+    loop {
+        interval.tick().await;
+        save_games(&games, &store, &mut saved_ticks).await;
+    }
+}
+
+/// Candy game server
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Directory with additional maze files, named by their file stem
+    #[arg(long)]
+    maze_dir: Option<PathBuf>,
+    /// Save recordings of finished games to this directory
+    #[arg(long)]
+    record_dir: Option<PathBuf>,
+    /// Keep games, player accounts and the leaderboard in this directory so that they survive server restarts
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
     env_logger::Builder::new()
         .filter(None, log::LevelFilter::Info)
         .init();
 
+    let mazes = match &args.maze_dir {
+        Some(dir) => MazeLibrary::with_dir(dir)?,
+        None => MazeLibrary::builtin(),
+    };
+    info!("Available mazes: {}", mazes.names().join(", "));
+    if let Some(dir) = &args.record_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let store = match &args.state_dir {
+        Some(dir) => Some(Arc::new(GameStore::new(dir)?)),
+        None => None,
+    };
+    let accounts = match &args.state_dir {
+        Some(dir) => Accounts::load(&dir.join("accounts.json"))?,
+        None => Accounts::in_memory(),
+    };
+    let accounts: SharedAccounts = Arc::new(Mutex::new(accounts));
+    let leaderboard = match &args.state_dir {
+        Some(dir) => Leaderboard::load(&dir.join("leaderboard.json"))?,
+        None => Leaderboard::in_memory(),
+    };
+    let leaderboard: SharedLeaderboard = Arc::new(Mutex::new(leaderboard));
     let games: SharedGames = Arc::new(Mutex::new(HashMap::new()));
+    if let Some(store) = &store {
+        tokio::spawn(persist_games(games.clone(), Arc::clone(store)));
+    }
+    let state = AppState {
+        games: games.clone(),
+        accounts: accounts.clone(),
+        leaderboard: leaderboard.clone(),
+        mazes: Arc::new(mazes),
+        record_dir: args.record_dir,
+        store: store.clone(),
+    };
 
-    tokio::spawn(clean_idle_players(games.clone()));
+    let restored = match &store {
+        Some(store) => restore_games(store)?,
+        None => Vec::new(),
+    };
+    // the games are locked until all are in so that none stops for not being found
+    let mut restored_games = games.lock().await;
+    for (game_id, game, ticks) in restored {
+        start_world(&state, game_id, &game, ticks);
+        tokio::spawn(record_results_when_finished(
+            games.clone(),
+            accounts.clone(),
+            leaderboard.clone(),
+            game_id,
+            game.updates.subscribe(),
+        ));
+        restored_games.insert(game_id, game);
+    }
+    drop(restored_games);
+
+    tokio::spawn(clean_idle_players(games.clone(), accounts.clone()));
+    tokio::spawn(persist_accounts(accounts.clone(), leaderboard.clone()));
+    tokio::spawn(archive_games(games.clone(), store.clone()));
 
     let app = Router::new()
         .route("/games", get(list_games))
+        .route("/mazes", get(list_mazes))
+        .route("/maze/:name", get(get_maze))
+        .route("/recordings", get(list_recordings))
+        .route("/recordings/:name", get(get_recording))
         .route("/create", post(create_game))
+        .route("/register", post(register))
+        .route("/login", post(login))
+        .route("/players/:name", get(get_player))
+        .route("/leaderboard", get(get_leaderboard))
         .route("/join", post(join_game))
+        .route("/spectate", post(spectate))
+        .route("/ready", post(player_ready))
+        .route("/games/:id/rematch", post(rematch))
         .route("/action", post(do_action))
         .route("/state", post(game_state))
-        .with_state(games);
+        .route("/ws", get(game_socket))
+        .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3030));
     info!("Starting server at {:?}", addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
         .await
         .unwrap();
+
+    if let Some(store) = &store {
+        info!("Saving games before shutdown");
+        save_games(&games, store, &mut HashMap::new()).await;
+    }
+    save_accounts(&accounts, &leaderboard).await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> AppState {
+        AppState {
+            games: Arc::new(Mutex::new(HashMap::new())),
+            accounts: Arc::new(Mutex::new(Accounts::in_memory())),
+            leaderboard: Arc::new(Mutex::new(Leaderboard::in_memory())),
+            mazes: Arc::new(MazeLibrary::builtin()),
+            record_dir: None,
+            store: None,
+        }
+    }
+
+    async fn create(state: &AppState, req: serde_json::Value) -> StatusCode {
+        let req = serde_json::from_value(req).unwrap();
+        create_game(State(state.clone()), Json(req))
+            .await
+            .into_response()
+            .status()
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn rematch_as(state: &AppState, game_id: u64, token: &str) -> StatusCode {
+        rematch(State(state.clone()), Path(game_id), bearer(token))
+            .await
+            .into_response()
+            .status()
+    }
+
+    fn generated_game() -> serde_json::Value {
+        serde_json::json!({
+            "name": "test",
+            "maze_name": "",
+            "mob_cnt": 0,
+            "candy_cnt": 0,
+            "generator": "backtracker",
+            "width": 11,
+            "height": 11,
+        })
+    }
+
+    /// Game with a single player who was last seen `idle_s` seconds ago, with their token
+    async fn game_with_idle_player(
+        state: &AppState,
+        phase: GamePhase,
+        idle_s: u64,
+    ) -> (u64, String) {
+        assert_eq!(create(state, generated_game()).await, StatusCode::OK);
+        let mut games = state.games.lock().await;
+        let (game_id, game) = games.iter_mut().next().unwrap();
+        let player_id = game.world.lock().await.spawn_player("alice").unwrap();
+        game.add_player(player_id, "alice", false);
+        let token = game.new_session(player_id);
+        game.set_phase(phase);
+        game.players_last_seen
+            .insert(player_id, Instant::now() - Duration::from_secs(idle_s));
+        (*game_id, token)
+    }
+
+    #[tokio::test]
+    async fn idle_players_are_removed() {
+        let state = test_state();
+        game_with_idle_player(&state, GamePhase::Running, CLIENT_MAX_PING_S + 1).await;
+        remove_idle_players(&state.games, &state.accounts).await;
+        let games = state.games.lock().await;
+        assert!(games.values().next().unwrap().players.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_busy_world_only_holds_up_its_own_game() {
+        let state = test_state();
+        let (busy_id, token) = game_with_idle_player(&state, GamePhase::Running, 0).await;
+        assert_eq!(create(&state, generated_game()).await, StatusCode::OK);
+        let (other_id, busy_world) = {
+            let games = state.games.lock().await;
+            let other_id = *games.keys().find(|id| **id != busy_id).unwrap();
+            (other_id, Arc::clone(&games[&busy_id].world))
+        };
+        let _moving = busy_world.lock().await;
+
+        let req = GetStateRequest {
+            since_tick: None,
+            follow: None,
+        };
+        let waiting = tokio::spawn(game_state(
+            State(Arc::clone(&state.games)),
+            bearer(&token),
+            Json(req),
+        ));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let req = JoinGameRequest {
+            game_id: other_id,
+            player_name: "bob".to_string(),
+        };
+        let join = join_game(
+            State(Arc::clone(&state.games)),
+            State(Arc::clone(&state.accounts)),
+            HeaderMap::new(),
+            Json(req),
+        );
+        let resp = tokio::time::timeout(Duration::from_secs(1), join)
+            .await
+            .expect("Joining waited for the other game's world");
+        assert_eq!(resp.into_response().status(), StatusCode::OK);
+        waiting.abort();
+    }
+
+    #[tokio::test]
+    async fn finished_games_can_be_rematched_after_the_idle_timeout() {
+        let state = test_state();
+        let (game_id, token) =
+            game_with_idle_player(&state, GamePhase::Finished, CLIENT_MAX_PING_S + 1).await;
+        remove_idle_players(&state.games, &state.accounts).await;
+
+        assert_eq!(rematch_as(&state, game_id, &token).await, StatusCode::OK);
+        assert_eq!(state.games.lock().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rematches_keep_the_accounts_of_logged_in_players() {
+        let state = test_state();
+        state
+            .accounts
+            .lock()
+            .await
+            .register("alice", "hash".to_string())
+            .unwrap();
+        assert_eq!(create(&state, generated_game()).await, StatusCode::OK);
+        let (game_id, tokens) = {
+            let mut games = state.games.lock().await;
+            let (game_id, game) = games.iter_mut().next().unwrap();
+            let world = Arc::clone(&game.world);
+            let mut world = world.lock().await;
+            let alice = world.spawn_player("alice").unwrap();
+            game.add_player(alice, "alice", true);
+            let bob = world.spawn_player("bob").unwrap();
+            game.add_player(bob, "bob", false);
+            game.set_phase(GamePhase::Finished);
+            let name = game.uncredited_account(alice).unwrap();
+            credit_account(&state.accounts, &name, &world.player_stats(alice)).await;
+            (*game_id, [game.new_session(alice), game.new_session(bob)])
+        };
+        // somebody else registered the guest's name after the game
+        state
+            .accounts
+            .lock()
+            .await
+            .register("bob", "hash".to_string())
+            .unwrap();
+
+        for token in &tokens {
+            assert_eq!(rematch_as(&state, game_id, token).await, StatusCode::OK);
+        }
+        let games = state.games.lock().await;
+        let rematch_game = games
+            .values()
+            .find(|game| game.phase == GamePhase::Lobby)
+            .unwrap();
+        let accounts: Vec<&String> = rematch_game.accounts.values().collect();
+        assert_eq!(accounts, ["alice"]);
+    }
+
+    #[tokio::test]
+    async fn long_countdowns_and_respawns_saturate() {
+        let state = test_state();
+        let mut req = generated_game();
+        req["countdown_s"] = u64::MAX.into();
+        req["respawn_s"] = u64::MAX.into();
+        assert_eq!(create(&state, req).await, StatusCode::OK);
+        let games = state.games.lock().await;
+        let game = games.values().next().unwrap();
+        assert_eq!(game.countdown_ms, u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn deathmatch_time_limits_are_counted_in_ticks() {
+        let state = test_state();
+        let mut req = generated_game();
+        req["mode"] = "deathmatch".into();
+        req["time_limit_s"] = 0.into();
+        assert_eq!(create(&state, req.clone()).await, StatusCode::BAD_REQUEST);
+
+        req["time_limit_s"] = 3.into();
+        req["tick_ms"] = 250.into();
+        assert_eq!(create(&state, req).await, StatusCode::OK);
+        let games = state.games.lock().await;
+        let world = games.values().next().unwrap().world.lock().await;
+        assert_eq!(world.recording_header().time_limit, Some(12));
+    }
+
+    #[tokio::test]
+    async fn out_of_range_ticks_are_rejected() {
+        let state = test_state();
+        for tick_ms in [0, MIN_TICK_MS - 1, MAX_TICK_MS + 1] {
+            let mut req = generated_game();
+            req["tick_ms"] = tick_ms.into();
+            assert_eq!(create(&state, req).await, StatusCode::BAD_REQUEST);
+        }
+    }
+}
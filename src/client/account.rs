@@ -0,0 +1,82 @@
+use anyhow::Result;
+use dialoguer::{Input, Password};
+use prettytable::{row, Table};
+
+use crate::game::api::{AccountRequest, LoginResponse, PlayerProfile};
+
+/// Account the client is logged in as
+pub struct Login {
+    pub name: String,
+    pub token: String,
+}
+
+fn read_account_input(confirm_password: bool) -> Result<AccountRequest> {
+    let name: String = Input::new().with_prompt("Name").interact_text()?;
+    let mut password = Password::new().with_prompt("Password");
+    if confirm_password {
+        password = password.with_confirmation("Repeat password", "Passwords don't match");
+    }
+    Ok(AccountRequest {
+        name,
+        password: password.interact()?,
+    })
+}
+
+pub async fn register(server: &str) -> Result<()> {
+    let req = read_account_input(true)?;
+    let url = format!("http://{}/register", server);
+    let resp = reqwest::Client::new().post(&url).json(&req).send().await?;
+
+    if resp.status().is_success() {
+        println!("Registered {}, log in to play under this name", req.name);
+    } else {
+        println!("Error registering: {}", resp.text().await?);
+    }
+    Ok(())
+}
+
+pub async fn login(server: &str) -> Result<Option<Login>> {
+    let req = read_account_input(false)?;
+    let url = format!("http://{}/login", server);
+    let resp = reqwest::Client::new().post(&url).json(&req).send().await?;
+
+    if !resp.status().is_success() {
+        println!("Error logging in: {}", resp.text().await?);
+        return Ok(None);
+    }
+    let resp: LoginResponse = resp.json().await?;
+    Ok(Some(Login {
+        name: req.name,
+        token: resp.token,
+    }))
+}
+
+pub async fn show_profile(server: &str, login: Option<&Login>) -> Result<()> {
+    let mut input = Input::new().with_prompt("Player name");
+    if let Some(login) = login {
+        input = input.default(login.name.clone());
+    }
+    let name: String = input.interact_text()?;
+
+    let url = format!("http://{}/players/{}", server, name);
+    let resp = reqwest::get(&url).await?;
+    if !resp.status().is_success() {
+        println!("{}", resp.text().await?);
+        return Ok(());
+    }
+    let profile: PlayerProfile = resp.json().await?;
+
+    let stats = &profile.stats;
+    let mut table = Table::new();
+    table.add_row(row!["Name", &profile.name]);
+    table.add_row(row!["Registered", &profile.registered]);
+    table.add_row(row!["Games played", stats.games_played]);
+    table.add_row(row!["Wins", stats.wins]);
+    table.add_row(row!["Deaths", stats.deaths]);
+    table.add_row(row!["Candies", stats.candies]);
+    table.add_row(row!["Mob kills", stats.mob_kills]);
+    table.add_row(row!["Player kills", stats.player_kills]);
+    table.printstd();
+
+    Ok(())
+}
@@ -1,55 +1,211 @@
 use anyhow::Result;
-use dialoguer::{Input, Select};
+use dialoguer::{Confirm, Input, Select};
 
-use crate::game::api::{CreateGameRequest, CreateGameResponse};
-use crate::game::mazes::MAZES;
+use crate::game::api::{CreateGameRequest, CreateGameResponse, GetMazesResponse};
+use crate::game::maze_gen::{MazeGenerator, MAX_SIZE};
+use crate::game::mob::{MobConfig, MobKind};
+use crate::game::pickup::{PickupConfig, PickupKind};
+use crate::game::rules::GameMode;
+use crate::game::visibility::Visibility;
+use crate::game::world_controller::{
+    DEFAULT_COUNTDOWN_S, DEFAULT_LIVES, DEFAULT_RESPAWN_S, DEFAULT_TICK_MS, DEFAULT_TIME_LIMIT_S,
+    MAX_TICK_MS, MIN_TICK_MS,
+};
 
-fn read_create_game_input() -> Result<CreateGameRequest> {
+fn read_create_game_input(server_mazes: &GetMazesResponse) -> Result<CreateGameRequest> {
     let name: String = Input::new().with_prompt("Game name").interact_text()?;
 
-    let available_mazes = MAZES.keys().copied().collect::<Vec<&str>>();
-    let maze_name: String = available_mazes
-        .get(
-            Select::new()
-                .with_prompt("Maze name")
-                .items(&available_mazes)
-                .default(0)
-                .interact()?,
-        )
-        .unwrap()
-        .to_string();
-
-    let mob_cnt: usize = Input::new()
-        .with_prompt("Mob count")
-        .default(10)
-        .interact_text()?;
+    let mut available_mazes = server_mazes
+        .mazes
+        .iter()
+        .map(|maze| format!("{} ({}x{})", maze.name, maze.width, maze.height))
+        .collect::<Vec<String>>();
+    available_mazes.extend(
+        MazeGenerator::ALL
+            .iter()
+            .map(|generator| format!("generated: {}", generator)),
+    );
+    let maze_idx = Select::new()
+        .with_prompt("Maze name")
+        .items(&available_mazes)
+        .default(0)
+        .interact()?;
+    let generator = maze_idx
+        .checked_sub(server_mazes.mazes.len())
+        .map(|idx| MazeGenerator::ALL[idx]);
+    let maze_name = match server_mazes.mazes.get(maze_idx) {
+        Some(maze) => maze.name.clone(),
+        None => available_mazes[maze_idx].clone(),
+    };
+
+    let mode_idx = Select::new()
+        .with_prompt("Game mode")
+        .items(&GameMode::ALL)
+        .default(0)
+        .interact()?;
+    let mode = GameMode::ALL[mode_idx];
+
+    let (mut width, mut height) = (0, 0);
+    if generator.is_some() {
+        let size_limit = |size: &usize| {
+            if *size <= MAX_SIZE {
+                Ok(())
+            } else {
+                Err(format!("At most {}", MAX_SIZE))
+            }
+        };
+        width = Input::new()
+            .with_prompt("Maze width")
+            .default(41)
+            .validate_with(size_limit)
+            .interact_text()?;
+        height = Input::new()
+            .with_prompt("Maze height")
+            .default(21)
+            .validate_with(size_limit)
+            .interact_text()?;
+    }
+
+    let mut mobs = Vec::new();
+    for kind in MobKind::ALL {
+        let count: usize = Input::new()
+            .with_prompt(format!("{} count", kind))
+            .default(if kind == MobKind::Walker { 10 } else { 0 })
+            .interact_text()?;
+        if count > 0 {
+            mobs.push(MobConfig::new(kind, count));
+        }
+    }
 
     let candy_cnt: usize = Input::new()
         .with_prompt("Candy count")
         .default(5)
         .interact_text()?;
 
+    let mut pickups = Vec::new();
+    for kind in PickupKind::ALL {
+        let count: usize = Input::new()
+            .with_prompt(format!("{} pickup count", kind))
+            .default(0)
+            .interact_text()?;
+        if count > 0 {
+            pickups.push(PickupConfig::new(kind, count));
+        }
+    }
+
+    let seed_input: String = Input::new()
+        .with_prompt("Seed (empty for random)")
+        .allow_empty(true)
+        .interact_text()?;
+    let seed = seed_input.trim().parse().ok();
+
+    let tick_ms: u64 = Input::new()
+        .with_prompt("Tick length (ms)")
+        .default(DEFAULT_TICK_MS)
+        .validate_with(|tick_ms: &u64| {
+            if (MIN_TICK_MS..=MAX_TICK_MS).contains(tick_ms) {
+                Ok(())
+            } else {
+                Err(format!("Between {} and {}", MIN_TICK_MS, MAX_TICK_MS))
+            }
+        })
+        .interact_text()?;
+
+    let countdown_s: u64 = Input::new()
+        .with_prompt("Countdown once all players are ready (s)")
+        .default(DEFAULT_COUNTDOWN_S)
+        .interact_text()?;
+
+    let lives: u32 = Input::new()
+        .with_prompt("Lives")
+        .default(DEFAULT_LIVES)
+        .interact_text()?;
+
+    let respawn_s: u64 = Input::new()
+        .with_prompt("Respawn delay (s)")
+        .default(DEFAULT_RESPAWN_S)
+        .interact_text()?;
+
+    let time_limit_s = if mode == GameMode::Deathmatch {
+        let time_limit_s: u64 = Input::new()
+            .with_prompt("Time limit (s)")
+            .default(DEFAULT_TIME_LIMIT_S)
+            .validate_with(|time_limit_s: &u64| {
+                if *time_limit_s > 0 {
+                    Ok(())
+                } else {
+                    Err("At least 1")
+                }
+            })
+            .interact_text()?;
+        Some(time_limit_s)
+    } else {
+        None
+    };
+
+    let visibility = if Confirm::new()
+        .with_prompt("Fog of war")
+        .default(false)
+        .interact()?
+    {
+        let radius: usize = Input::new()
+            .with_prompt("Sight radius (0 for unlimited)")
+            .default(6)
+            .interact_text()?;
+        let line_of_sight = Confirm::new()
+            .with_prompt("Walls block the view")
+            .default(true)
+            .interact()?;
+        Some(Visibility {
+            radius: (radius > 0).then_some(radius),
+            line_of_sight,
+        })
+    } else {
+        None
+    };
+
     Ok(CreateGameRequest {
         name,
         maze_name,
-        mob_cnt,
+        mob_cnt: 0,
         candy_cnt,
+        mobs,
+        pickups,
+        generator,
+        width,
+        height,
+        seed,
+        tick_ms: Some(tick_ms),
+        countdown_s: Some(countdown_s),
+        mode,
+        lives: Some(lives),
+        respawn_s: Some(respawn_s),
+        visibility,
+        time_limit_s,
     })
 }
 
 pub async fn create_game(server: &str) -> Result<()> {
+    let url = format!("http://{}/mazes", server);
+    let server_mazes: GetMazesResponse = reqwest::get(&url).await?.json().await?;
+
     let url = format!("http://{}/create", server);
 
-    let req = read_create_game_input()?;
+    let req = read_create_game_input(&server_mazes)?;
 
-    let resp: CreateGameResponse = reqwest::Client::new()
+    let resp = reqwest::Client::new()
         .post(&url)
         .json(&req)
         .send()
         .await
-        .expect("Couldn't connect to server to create game")
-        .json()
-        .await?;
+        .expect("Couldn't connect to server to create game");
+
+    if resp.status().is_client_error() {
+        println!("Error creating game: {}", resp.text().await?);
+        return Ok(());
+    }
+
+    let resp: CreateGameResponse = resp.json().await?;
     println!("Created game with id: {}", resp.game_id);
     Ok(())
 }
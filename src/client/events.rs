@@ -0,0 +1,52 @@
+use crate::game::event::{GameEvent, Killer, LoggedEvent};
+
+/// Human readable line describing the event
+pub fn describe(event: &GameEvent) -> String {
+    match event {
+        GameEvent::PlayerJoined { player } => format!("Player {} entered world", player.name),
+        GameEvent::PlayerLeft { player } => format!("Player {} left the game", player.name),
+        GameEvent::CandyCollected { player, remaining } => {
+            format!("{} got a candy, {} left", player.name, remaining)
+        }
+        GameEvent::MobKilled { kind, by } => match by {
+            Killer::Player { player } => format!("{} killed a {}", player.name, kind),
+            Killer::Shot {
+                shooter: Some(shooter),
+            } => format!("{}'s shot killed a {}", shooter.name, kind),
+            Killer::Shot { shooter: None } => format!("A {} killed by stray shot", kind),
+            Killer::Mob { kind: killer } => format!("A {} killed by a {}", kind, killer),
+        },
+        GameEvent::PlayerKilled { player, by } => match by {
+            Killer::Mob { kind } => format!("{} killed by {}", player.name, kind),
+            Killer::Player { player: killer } => format!("{} killed {}", killer.name, player.name),
+            Killer::Shot {
+                shooter: Some(shooter),
+            } if shooter.id == player.id => format!("{} shot themselves", player.name),
+            Killer::Shot {
+                shooter: Some(shooter),
+            } => format!("{} killed by {}'s shot", player.name, shooter.name),
+            Killer::Shot { shooter: None } => format!("{} killed by stray shot", player.name),
+        },
+        GameEvent::PlayerRespawned { player, lives } => {
+            format!("{} is back, lives left: {}", player.name, lives)
+        }
+        GameEvent::PickupCollected { player, kind } => {
+            format!("{} picked up {}", player.name, kind)
+        }
+        GameEvent::EffectEnded { player, kind } => {
+            format!("{}'s {} wore off", player.name, kind)
+        }
+        GameEvent::DoorOpened { player } => format!("{} opened a door", player.name),
+        GameEvent::ExitBlocked { player, remaining } => format!(
+            "You need to collect {} more candies, {}",
+            remaining, player.name
+        ),
+        GameEvent::PlayerExited { player } => format!("{} reached the exit", player.name),
+        GameEvent::PlayerWon { player } => format!("Player {} won the game", player.name),
+    }
+}
+
+/// Event prefixed with the server time it happened at
+pub fn describe_logged(event: &LoggedEvent) -> String {
+    format!("{}: {}", event.time, describe(&event.event))
+}
@@ -1,22 +1,158 @@
 use anyhow::{anyhow, Result};
 use dialoguer::{Input, Select};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
 use std::fmt;
-use std::io::{stdin, stdout, Write};
+use std::io::stdin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use termion::event::Key;
 use termion::input::TermRead;
-use termion::raw::IntoRawMode;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::time::{sleep, Duration, Instant};
-
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::client::account::Login;
+use crate::client::events::describe_logged;
+use crate::client::maze_cache::MazeCache;
+use crate::client::renderer::{Frame, Renderer, Style};
+use crate::client::scoreboard::{draw_score_panel, draw_scoreboard};
+use crate::client::state::GameView;
+use crate::client::theme::Theme;
+use crate::client::viewport::Viewport;
 use crate::game::api::{
-    ActionRequest, GameInfo, GetGamesResponse, GetStateRequest, GetStateResponse, JoinGameRequest,
-    JoinGameResponse, PlayerAction,
+    ActionRequest, FollowRequest, GameInfo, GamePhase, GetGamesResponse, GetStateRequest,
+    GetStateResponse, JoinGameRequest, JoinGameResponse, PlayerAction, PlayerScore,
+    SpectateRequest, SpectateResponse,
 };
-use crate::game::map::{Direction, Map};
-use crate::game::mazes::MAZES;
+use crate::game::map::{Direction, Map, Point};
+use crate::game::maze_gen::MazeSpec;
+use crate::game::maze_loader::{self, MazeLibrary};
+
+/// What the player asked for with a key press
+enum Command {
+    Action(PlayerAction),
+    /// Ready to start the game, in the lobby
+    Ready,
+}
+
+/// Cells the free camera moves with a single key press
+const PAN_STEP: usize = 5;
+
+/// Player a spectator follows, chosen among the players in the game
+#[derive(Default)]
+struct Camera {
+    /// Players in the game ordered by name, to cycle through
+    players: Vec<u64>,
+    /// `None` for the free camera
+    follow: Option<u64>,
+    /// Where the free camera looks, kept in step with the viewport while following players
+    free_focus: Point,
+}
+
+impl Camera {
+    fn set_players(&mut self, scores: &[PlayerScore]) {
+        let mut scores: Vec<&PlayerScore> = scores.iter().collect();
+        scores.sort_by(|a, b| a.name.cmp(&b.name));
+        self.players = scores.iter().map(|score| score.player_id).collect();
+        // players who left can't be followed anymore
+        if !self.follow.is_some_and(|id| self.players.contains(&id)) {
+            self.follow = None;
+        }
+    }
+
+    /// Follows the next player, or the previous one for a negative `step`
+    fn cycle(&mut self, step: isize) {
+        let len = self.players.len() as isize;
+        if len == 0 {
+            return;
+        }
+        let followed = self
+            .follow
+            .and_then(|id| self.players.iter().position(|player| *player == id));
+        let next = match followed {
+            Some(idx) => (idx as isize + step).rem_euclid(len),
+            None if step > 0 => 0,
+            None => len - 1,
+        };
+        self.follow = Some(self.players[next as usize]);
+    }
+
+    /// Moves the free camera, leaving the followed player where they are
+    fn pan(&mut self, dir: Direction) {
+        self.follow = None;
+        let focus = &mut self.free_focus;
+        match dir {
+            Direction::Up => focus.y = focus.y.saturating_sub(PAN_STEP),
+            Direction::Down => focus.y += PAN_STEP,
+            Direction::Left => focus.x = focus.x.saturating_sub(PAN_STEP),
+            Direction::Right => focus.x += PAN_STEP,
+        }
+    }
+}
+
+/// Whom the game is shown to, a player or a spectator
+struct Session<'a> {
+    token: &'a str,
+    tick_ms: u64,
+    /// Only spectators have a camera, it's moved by their key presses
+    camera: Option<Arc<Mutex<Camera>>>,
+    theme: &'a Theme,
+}
+
+impl Session<'_> {
+    fn spectating(&self) -> bool {
+        self.camera.is_some()
+    }
+
+    fn following(&self) -> Option<u64> {
+        let camera = self.camera.as_ref()?;
+        camera.lock().expect("Camera lock poisoned").follow
+    }
+
+    fn track_players(&self, scores: &[PlayerScore]) {
+        if let Some(camera) = &self.camera {
+            camera
+                .lock()
+                .expect("Camera lock poisoned")
+                .set_players(scores);
+        }
+    }
+
+    /// Fits the viewport to the terminal of `size`, which may have been resized, and scrolls it
+    /// to the player, the followed player or the free camera
+    fn scroll(
+        &self,
+        viewport: &mut Viewport,
+        map: &Map,
+        view: &GameView,
+        state: &GetStateResponse,
+        size: (u16, u16),
+    ) {
+        let Some(camera) = &self.camera else {
+            let focus = state.player_id.and_then(|id| view.position(id));
+            viewport.update(map, size, focus);
+            return;
+        };
+        let mut camera = camera.lock().expect("Camera lock poisoned");
+        let focus = match camera.follow {
+            Some(id) => view.position(id),
+            None => Some(camera.free_focus),
+        };
+        viewport.update(map, size, focus);
+        // the free camera starts from what's on screen and never leaves the maze
+        camera.free_focus = viewport.center();
+    }
+}
 
-fn read_keystrokes(tx: Sender<PlayerAction>) -> Result<()> {
+/// Reads keys until the player leaves, returns whether they want to play again. That's only
+/// possible once the game is `finished`.
+fn read_keystrokes(tx: Sender<Command>, finished: Arc<AtomicBool>) -> Result<bool> {
     let mut keys = stdin().keys();
     let mut prev_press = Instant::now();
 
@@ -27,149 +163,498 @@ fn read_keystrokes(tx: Sender<PlayerAction>) -> Result<()> {
             continue;
         }
 
-        let action: PlayerAction = match key? {
+        let command = match key? {
             Key::Esc | Key::Char('q') | Key::Ctrl('c') => {
                 break;
             }
-            Key::Left => PlayerAction::Move(Direction::Left),
-            Key::Right => PlayerAction::Move(Direction::Right),
-            Key::Up => PlayerAction::Move(Direction::Up),
-            Key::Down => PlayerAction::Move(Direction::Down),
-            Key::Char(' ') => PlayerAction::Shoot,
+            Key::Left => Command::Action(PlayerAction::Move(Direction::Left)),
+            Key::Right => Command::Action(PlayerAction::Move(Direction::Right)),
+            Key::Up => Command::Action(PlayerAction::Move(Direction::Up)),
+            Key::Down => Command::Action(PlayerAction::Move(Direction::Down)),
+            Key::Char(' ') => Command::Action(PlayerAction::Shoot),
+            Key::Char('r') => Command::Ready,
+            Key::Char('p') if finished.load(Ordering::Relaxed) => return Ok(true),
             _ => continue,
         };
 
-        tx.blocking_send(action)?;
+        tx.blocking_send(command)?;
 
         prev_press = Instant::now();
     }
-    Ok(())
+    Ok(false)
 }
 
-async fn send_player_actions(
-    mut rx: Receiver<PlayerAction>,
-    server: &str,
-    game_id: u64,
-    player_id: u64,
-) -> Result<()> {
+async fn send_ready(client: &reqwest::Client, server: &str, token: &str) {
+    let url = format!("http://{}/ready", server);
+    // the game may have started already, there is nothing to do about it then
+    let _ = client
+        .post(&url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Couldn't connect to server to get ready");
+}
+
+async fn send_player_actions(mut rx: Receiver<Command>, server: &str, token: &str) -> Result<()> {
     let client = reqwest::Client::new();
-    let mut should_send = true;
     loop {
-        let action = rx.recv().await.ok_or(anyhow!("No msg received"))?;
-
-        // if server responded with error, it means the player is dead
-        if !should_send {
-            continue;
-        }
+        let action = match rx.recv().await.ok_or(anyhow!("No msg received"))? {
+            Command::Action(action) => action,
+            Command::Ready => {
+                send_ready(&client, server, token).await;
+                continue;
+            }
+        };
 
+        // actions are rejected outside of running games and from dead players
         let url = format!("http://{}/action", server);
-        let req = ActionRequest {
-            game_id,
-            player_id,
-            action,
-        };
-        should_send = client
+        let req = ActionRequest { action };
+        let _ = client
             .post(&url)
+            .bearer_auth(token)
             .json(&req)
             .send()
             .await
-            .expect("Couldn't connect to server to send action")
-            .error_for_status()
-            .is_ok();
+            .expect("Couldn't connect to server to send action");
     }
 }
 
-async fn handle_player_input(server: &str, game_id: u64, player_id: u64) -> Result<()> {
+async fn handle_player_input(server: &str, token: &str, finished: Arc<AtomicBool>) -> Result<bool> {
     let (tx, rx) = mpsc::channel(1);
 
-    let blocking_read = tokio::task::spawn_blocking(move || {
-        let _ = read_keystrokes(tx);
-    });
+    let blocking_read =
+        tokio::task::spawn_blocking(move || read_keystrokes(tx, finished).unwrap_or(false));
 
     tokio::select! {
-        _ = blocking_read => {},
-        _ = send_player_actions(rx, server, game_id, player_id) => {},
+        play_again = blocking_read => Ok(play_again?),
+        _ = send_player_actions(rx, server, token) => Ok(false),
     }
+}
 
+/// Moves the spectator's camera until they leave
+fn read_spectator_keys(camera: Arc<Mutex<Camera>>) -> Result<()> {
+    let mut keys = stdin().keys();
+    loop {
+        let key = keys.next().ok_or(anyhow!("No key pressed"))?;
+        let mut camera = camera.lock().expect("Camera lock poisoned");
+        match key? {
+            Key::Esc | Key::Char('q') | Key::Ctrl('c') => break,
+            Key::Char('\t') => camera.cycle(1),
+            Key::BackTab => camera.cycle(-1),
+            Key::Left => camera.pan(Direction::Left),
+            Key::Right => camera.pan(Direction::Right),
+            Key::Up => camera.pan(Direction::Up),
+            Key::Down => camera.pan(Direction::Down),
+            Key::Char('f') => camera.follow = None,
+            _ => {}
+        }
+    }
     Ok(())
 }
 
-async fn show_map_loop(server: &str, game_id: u64, player_id: u64, map: Map) -> Result<()> {
-    let mut stdout = stdout().into_raw_mode()?;
+async fn show_map_loop(
+    server: &str,
+    session: &Session<'_>,
+    map: Map,
+    finished: &AtomicBool,
+) -> Result<()> {
+    let mut renderer = Renderer::new()?;
 
     let client = reqwest::Client::new();
+    let mut view = GameView::default();
+    let mut viewport = Viewport::default();
+    let mut followed = None;
 
     loop {
+        // deltas are limited to what the followed player sees, so a new one starts from scratch
+        let follow = session.following();
+        if follow != followed {
+            view = GameView::default();
+            followed = follow;
+        }
         let url = format!("http://{}/state", server);
-        let req = GetStateRequest { game_id, player_id };
+        let req = GetStateRequest {
+            since_tick: view.tick(),
+            follow,
+        };
 
         let state: GetStateResponse = client
             .post(&url)
+            .bearer_auth(session.token)
             .json(&req)
             .send()
             .await
             .expect("Couldn't connect to server to get state")
+            .error_for_status()?
             .json()
             .await?;
 
-        let map = map.clone().place_objects(state.objects);
-
-        write!(
-            stdout,
-            "{}{}{}",
-            termion::clear::All,
-            termion::cursor::Goto(1, 1),
-            map.format()
-        )?;
-
-        for (i, log) in state.logs.iter().rev().take(map.height()).enumerate() {
-            write!(
-                stdout,
-                "{}{}\r\n",
-                termion::cursor::Goto(map.width() as u16 + 2, 1 + i as u16),
-                log
-            )?;
-        }
-
-        let splash_msg: Option<&str> = if state.player_dead {
-            Some("YOU DIED!")
-        } else if state.player_winner {
-            Some("YOU WON!")
-        } else if state.is_finished {
-            Some("GAME OVER!")
+        view.apply(&state);
+        view.look(&map, &state);
+        session.track_players(&state.scores);
+        if state.is_finished {
+            finished.store(true, Ordering::Relaxed);
+            let mut frame = renderer.frame();
+            show_final_scores(&mut frame, &state, session);
+            renderer.draw(frame)?;
+            // keep the scoreboard up until the player quits or plays again
+            return std::future::pending().await;
+        }
+        let mut frame = renderer.frame();
+        session.scroll(&mut viewport, &map, &view, &state, frame.size());
+        draw_game(&mut frame, &map, &view, &state, &viewport, session);
+        renderer.draw(frame)?;
+
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn show_final_scores(frame: &mut Frame, state: &GetStateResponse, session: &Session) {
+    if session.spectating() {
+        return draw_scoreboard(frame, "GAME OVER!", &state.scores, None, session.tick_ms);
+    }
+    let title = if state.player_winner {
+        "YOU WON!"
+    } else {
+        "GAME OVER!"
+    };
+    draw_scoreboard(
+        frame,
+        title,
+        &state.scores,
+        state.player_id,
+        session.tick_ms,
+    )
+}
+
+fn draw_game(
+    frame: &mut Frame,
+    map: &Map,
+    view: &GameView,
+    state: &GetStateResponse,
+    viewport: &Viewport,
+    session: &Session,
+) {
+    match state.phase {
+        GamePhase::Lobby | GamePhase::Countdown => draw_lobby(frame, view, state, session),
+        _ => draw_state(frame, map, view, state, viewport, session),
+    }
+}
+
+fn draw_lobby(frame: &mut Frame, view: &GameView, state: &GetStateResponse, session: &Session) {
+    let spectating = session.spectating();
+    let title = match state.starts_in_ms {
+        Some(ms) => format!("Starting in {}s", ms.div_ceil(1000)),
+        None => "Waiting for all players to get ready".to_string(),
+    };
+    frame.text(1, 1, &title);
+    for (i, score) in state.scores.iter().enumerate() {
+        let ready = if state.ready.contains(&score.player_id) {
+            "ready"
+        } else {
+            "not ready"
+        };
+        let you = if !spectating && Some(score.player_id) == state.player_id {
+            " (you)"
         } else {
-            None
+            ""
         };
+        let team = match score.team {
+            Some(team) => format!("{:<6}", team),
+            None => String::new(),
+        };
+        let name = format!("{:<20} ", score.name);
+        let style = session
+            .theme
+            .player_style(view.player_slots().get(score.player_id));
+        let y = 3 + i as u16;
+        frame.styled_text(1, y, &name, style);
+        let line = format!("{}{}{}", team, ready, you);
+        frame.text(name.chars().count() as u16 + 1, y, &line);
+    }
+    let hint = if spectating {
+        "Press q to leave"
+    } else {
+        "Press r when ready, q to leave"
+    };
+    frame.text(1, 4 + state.scores.len() as u16, hint);
+}
+
+/// Glyph of the cell, cells out of sight are drawn dimmed from memory, without the objects in
+/// them, or not at all
+fn format_cell(map: &Map, view: &GameView, pos: &Point, theme: &Theme) -> (char, Style) {
+    let object = map.get_object(pos);
+    let (glyph, style) = theme.draw(object.type_, view.player_slots().get(object.id));
+    match view.visible() {
+        Some(visible) if !visible.contains(pos) => {
+            if view.explored().contains(pos) {
+                let faint = Style {
+                    faint: true,
+                    ..style
+                };
+                (glyph, faint)
+            } else {
+                (' ', Style::default())
+            }
+        }
+        _ => (glyph, style),
+    }
+}
 
-        if let Some(msg) = splash_msg {
-            write!(
-                stdout,
-                "{}{}{}",
-                termion::cursor::Goto((map.width() as u16) / 2 - 3, (map.height() / 2) as u16),
-                msg,
-                termion::cursor::Goto(map.width() as u16, map.height() as u16)
-            )?;
+fn draw_state(
+    frame: &mut Frame,
+    map: &Map,
+    view: &GameView,
+    state: &GetStateResponse,
+    viewport: &Viewport,
+    session: &Session,
+) {
+    let spectating = session.spectating();
+    let map = view.place_objects(map.clone());
+    for (y, cells) in viewport.rows().enumerate() {
+        for (x, pos) in cells.enumerate() {
+            let (glyph, style) = format_cell(&map, view, &pos, session.theme);
+            frame.put(x as u16 + 1, y as u16 + 1, glyph, style);
         }
+    }
 
-        write!(
-            stdout,
-            "{}",
-            termion::cursor::Goto(0, (map.height() + 1) as u16),
-        )?;
+    // the panel stays docked right of the viewport, whatever the size of the maze
+    let panel_x = viewport.panel_x();
+    // scores on top, the latest events below them
+    let events_y = draw_score_panel(
+        frame,
+        panel_x,
+        &state.scores,
+        state.player_id,
+        session.theme,
+        view.player_slots(),
+    ) + 2;
+    let event_cnt = viewport.height().saturating_sub(events_y as usize - 1);
+    for (i, event) in view.events().iter().rev().take(event_cnt).enumerate() {
+        frame.text(panel_x, events_y + i as u16, &describe_logged(event));
+    }
 
-        stdout.flush()?;
+    let followed = state
+        .scores
+        .iter()
+        .find(|score| Some(score.player_id) == state.player_id);
+    let who = match followed {
+        Some(score) if spectating => score.name.to_uppercase(),
+        _ => "YOU".to_string(),
+    };
+    // finished games are shown on the scoreboard instead
+    let splash = match state.respawn_in_ms {
+        _ if state.player_dead => Some(format!("{} DIED!", who)),
+        Some(ms) => Some(format!("BACK IN {}s", ms.div_ceil(1000))),
+        None => None,
+    };
+    if let Some(splash) = splash {
+        let x = viewport.width().saturating_sub(splash.len()) / 2;
+        let y = (viewport.height() / 2).max(1);
+        frame.text(x as u16 + 1, y as u16, &splash);
+    }
+
+    let mut status = match followed {
+        Some(score) if spectating => format!("Following {}  Lives: {}", score.name, state.lives),
+        None if spectating => "Free camera".to_string(),
+        _ => format!("Lives: {}", state.lives),
+    };
+    if state.keys > 0 {
+        status.push_str(&format!("  Keys: {}", state.keys));
+    }
+    for effect in &state.effects {
+        let secs = effect.remaining_ms as f64 / 1000.0;
+        status.push_str(&format!("  {} {:.1}s", effect.kind, secs));
+    }
+    if spectating {
+        status.push_str("   (Tab - next player, arrows - move camera, f - free camera, q - leave)");
+    }
+    frame.text(1, viewport.height() as u16 + 1, &status);
+}
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn send_player_actions_ws(
+    mut rx: Receiver<Command>,
+    mut sink: SplitSink<Socket, Message>,
+    server: &str,
+    token: &str,
+) -> Result<()> {
+    let client = reqwest::Client::new();
+    while let Some(command) = rx.recv().await {
+        match command {
+            Command::Action(action) => {
+                sink.send(Message::Text(serde_json::to_string(&action)?))
+                    .await?
+            }
+            Command::Ready => send_ready(&client, server, token).await,
+        }
+    }
+    Ok(())
+}
 
+/// Tells the server whenever the spectator follows somebody else
+async fn send_follow_ws(
+    mut sink: SplitSink<Socket, Message>,
+    camera: &Mutex<Camera>,
+) -> Result<()> {
+    let mut followed = None;
+    loop {
+        let follow = camera.lock().expect("Camera lock poisoned").follow;
+        if follow != followed {
+            let req = FollowRequest { player_id: follow };
+            sink.send(Message::Text(serde_json::to_string(&req)?))
+                .await?;
+            followed = follow;
+        }
         sleep(Duration::from_millis(50)).await;
     }
 }
 
+async fn show_map_ws(
+    mut stream: SplitStream<Socket>,
+    session: &Session<'_>,
+    map: Map,
+    finished: &AtomicBool,
+) -> Result<()> {
+    let mut renderer = Renderer::new()?;
+    let mut view = GameView::default();
+    let mut viewport = Viewport::default();
+
+    while let Some(msg) = stream.next().await {
+        if let Message::Text(text) = msg? {
+            let state: GetStateResponse = serde_json::from_str(&text)?;
+            view.apply(&state);
+            view.look(&map, &state);
+            session.track_players(&state.scores);
+            if state.is_finished {
+                finished.store(true, Ordering::Relaxed);
+                let mut frame = renderer.frame();
+                show_final_scores(&mut frame, &state, session);
+                renderer.draw(frame)?;
+                return std::future::pending().await;
+            }
+            let mut frame = renderer.frame();
+            session.scroll(&mut viewport, &map, &view, &state, frame.size());
+            draw_game(&mut frame, &map, &view, &state, &viewport, session);
+            renderer.draw(frame)?;
+        }
+    }
+    Ok(())
+}
+
+/// Plays the game over a single websocket, the server pushes state after every tick
+async fn play_over_ws(
+    socket: Socket,
+    server: &str,
+    session: &Session<'_>,
+    map: Map,
+) -> Result<bool> {
+    let (sink, stream) = socket.split();
+    let (tx, rx) = mpsc::channel(1);
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let finished_clone = Arc::clone(&finished);
+    let blocking_read =
+        tokio::task::spawn_blocking(move || read_keystrokes(tx, finished_clone).unwrap_or(false));
+
+    tokio::select! {
+        play_again = blocking_read => Ok(play_again?),
+        _ = show_map_ws(stream, session, map, &finished) => Ok(false),
+        _ = send_player_actions_ws(rx, sink, server, session.token) => Ok(false),
+    }
+}
+
+async fn connect_ws(server: &str, token: &str) -> Result<Socket> {
+    let mut ws_request = format!("ws://{}/ws", server).into_client_request()?;
+    ws_request
+        .headers_mut()
+        .insert(AUTHORIZATION, format!("Bearer {}", token).parse()?);
+    let (socket, _) = connect_async(ws_request).await?;
+    Ok(socket)
+}
+
+/// Plays the joined game until the player leaves, returns whether they want to play again
+async fn play(server: &str, joined: &JoinGameResponse, map: Map, theme: &Theme) -> Result<bool> {
+    let session = Session {
+        token: &joined.token,
+        tick_ms: joined.tick_ms,
+        camera: None,
+        theme,
+    };
+    match connect_ws(server, &joined.token).await {
+        Ok(socket) => play_over_ws(socket, server, &session, map).await,
+        Err(_) => {
+            // fall back to polling the state over plain HTTP
+            let finished = Arc::new(AtomicBool::new(false));
+            tokio::select! {
+                _ = show_map_loop(server, &session, map, &finished) => Ok(false),
+                play_again = handle_player_input(server, &joined.token, Arc::clone(&finished)) => {
+                    play_again
+                }
+            }
+        }
+    }
+}
+
+/// Shows the game to the spectator until they leave, never sending any actions
+async fn watch(server: &str, spectating: &SpectateResponse, map: Map, theme: &Theme) -> Result<()> {
+    let camera = Arc::new(Mutex::new(Camera::default()));
+    let session = Session {
+        token: &spectating.token,
+        tick_ms: spectating.tick_ms,
+        camera: Some(Arc::clone(&camera)),
+        theme,
+    };
+    let finished = AtomicBool::new(false);
+
+    let camera_clone = Arc::clone(&camera);
+    let blocking_read = tokio::task::spawn_blocking(move || read_spectator_keys(camera_clone));
+
+    match connect_ws(server, &spectating.token).await {
+        Ok(socket) => {
+            let (sink, stream) = socket.split();
+            tokio::select! {
+                read = blocking_read => read?,
+                _ = show_map_ws(stream, &session, map, &finished) => Ok(()),
+                _ = send_follow_ws(sink, &camera) => Ok(()),
+            }
+        }
+        Err(_) => tokio::select! {
+            read = blocking_read => read?,
+            _ = show_map_loop(server, &session, map, &finished) => Ok(()),
+        },
+    }
+}
+
+/// Moves the player to a rematch of the finished game
+async fn request_rematch(
+    server: &str,
+    joined: &JoinGameResponse,
+) -> Result<Option<JoinGameResponse>> {
+    let url = format!("http://{}/games/{}/rematch", server, joined.game_id);
+    let resp = reqwest::Client::new()
+        .post(&url)
+        .bearer_auth(&joined.token)
+        .send()
+        .await
+        .expect("Couldn't connect to server to play again");
+
+    if !resp.status().is_success() {
+        println!("Error starting a rematch: {}", resp.text().await?);
+        return Ok(None);
+    }
+    Ok(Some(resp.json().await?))
+}
+
 impl fmt::Display for GameInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {} ({})", self.id, self.name, self.maze_name)
     }
 }
 
-pub async fn join_game(server: &str) -> Result<()> {
+/// Lets the user pick one of the server's games, `None` if there are none
+async fn choose_game(server: &str) -> Result<Option<u64>> {
     let url = format!("http://{}/games", server);
     let available_games = reqwest::get(&url)
         .await?
@@ -180,27 +665,76 @@ pub async fn join_game(server: &str) -> Result<()> {
 
     if available_games.is_empty() {
         println!("No games available");
-        return Ok(());
+        return Ok(None);
     }
 
-    let url = format!("http://{}/join", server);
-
     let game_idx: usize = Select::new()
         .with_prompt("Choose game")
         .items(&available_games)
         .default(0)
         .interact()?;
-    let game_id = available_games.get(game_idx).unwrap().id;
-    let player_name: String = Input::new().with_prompt("Player name").interact_text()?;
+    Ok(Some(available_games.get(game_idx).unwrap().id))
+}
+
+/// Builds the game's maze without doors and pickups, `None` if the template couldn't be loaded.
+/// Mazes of the library are used if they are the same as the server's, others are downloaded.
+async fn load_map(
+    server: &str,
+    mazes: &MazeLibrary,
+    maze_name: &str,
+    maze_hash: &str,
+    maze_spec: Option<&MazeSpec>,
+) -> Option<Map> {
+    let local = mazes
+        .get(maze_name)
+        .filter(|template| maze_loader::maze_hash(template) == maze_hash)
+        .and_then(|_| mazes.map(maze_name).ok());
+    let mut map = match (maze_spec, local) {
+        (Some(spec), _) => Map::generate(spec),
+        (None, Some(map)) => map,
+        (None, None) => match MazeCache::default()
+            .get_or_fetch(server, maze_name, maze_hash)
+            .await
+        {
+            Ok(map) => map,
+            Err(err) => {
+                println!("Could not load maze template: {}", err);
+                return None;
+            }
+        },
+    };
+
+    // doors and pickups are sent as objects, opened or collected ones must not stay in the maze
+    map.take_items();
+    Some(map)
+}
+
+pub async fn join_game(
+    server: &str,
+    mazes: &MazeLibrary,
+    login: Option<&Login>,
+    theme: &Theme,
+) -> Result<()> {
+    let Some(game_id) = choose_game(server).await? else {
+        return Ok(());
+    };
+    // logged in players always play under their account name
+    let player_name = match login {
+        Some(login) => login.name.clone(),
+        None => Input::new().with_prompt("Player name").interact_text()?,
+    };
 
     let req = JoinGameRequest {
         game_id,
         player_name,
     };
 
-    let resp = reqwest::Client::new()
-        .post(&url)
-        .json(&req)
+    let url = format!("http://{}/join", server);
+    let mut join_req = reqwest::Client::new().post(&url).json(&req);
+    if let Some(login) = login {
+        join_req = join_req.bearer_auth(&login.token);
+    }
+    let resp = join_req
         .send()
         .await
         .expect("Couldn't connect to server to join game");
@@ -211,17 +745,63 @@ pub async fn join_game(server: &str) -> Result<()> {
     }
 
     let resp: JoinGameResponse = resp.json().await?;
-    if !MAZES.contains_key(&resp.maze_name) {
-        println!("Could not find maze template: {}", resp.maze_name);
+    let Some(map) = load_map(
+        server,
+        mazes,
+        &resp.maze_name,
+        &resp.maze_hash,
+        resp.maze_spec.as_ref(),
+    )
+    .await
+    else {
         return Ok(());
-    }
+    };
 
     println!("Joined with player id: {}", resp.player_id);
 
-    tokio::select! {
-        _ = show_map_loop(server, game_id, resp.player_id, Map::new(&resp.maze_name)) => {},
-        _ = handle_player_input(server, game_id, resp.player_id) => {},
-    };
+    // rematches are played on the same maze
+    let mut joined = resp;
+    while play(server, &joined, map.clone(), theme).await? {
+        match request_rematch(server, &joined).await? {
+            Some(rematch) => joined = rematch,
+            None => break,
+        }
+    }
 
     Ok(())
 }
+
+/// Watches a game without joining it
+pub async fn spectate_game(server: &str, mazes: &MazeLibrary, theme: &Theme) -> Result<()> {
+    let Some(game_id) = choose_game(server).await? else {
+        return Ok(());
+    };
+
+    let url = format!("http://{}/spectate", server);
+    let resp = reqwest::Client::new()
+        .post(&url)
+        .json(&SpectateRequest { game_id })
+        .send()
+        .await
+        .expect("Couldn't connect to server to spectate game");
+
+    if resp.status().is_client_error() {
+        println!("Error spectating game: {}", resp.text().await?);
+        return Ok(());
+    }
+
+    let resp: SpectateResponse = resp.json().await?;
+    let Some(map) = load_map(
+        server,
+        mazes,
+        &resp.maze_name,
+        &resp.maze_hash,
+        resp.maze_spec.as_ref(),
+    )
+    .await
+    else {
+        return Ok(());
+    };
+
+    watch(server, &resp, map, theme).await
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use dialoguer::Input;
+use prettytable::{row, Table};
+
+use crate::game::api::{LeaderboardEntry, LeaderboardResponse};
+
+fn print_category(title: &str, entries: &[LeaderboardEntry], format_value: impl Fn(u64) -> String) {
+    println!("{}", title);
+    if entries.is_empty() {
+        println!("No results yet");
+        return;
+    }
+    let mut table = Table::new();
+    table.add_row(row!["#", "Player", "Maze name", "Date", title]);
+    for (i, entry) in entries.iter().enumerate() {
+        table.add_row(row![
+            i + 1,
+            &entry.player,
+            &entry.maze_name,
+            &entry.date,
+            r->format_value(entry.value)
+        ]);
+    }
+    table.printstd();
+}
+
+pub async fn show_leaderboard(server: &str) -> Result<()> {
+    let maze: String = Input::new()
+        .with_prompt("Maze name (empty for all mazes)")
+        .allow_empty(true)
+        .interact_text()?;
+
+    let url = format!("http://{}/leaderboard", server);
+    let mut req = reqwest::Client::new().get(&url);
+    if !maze.is_empty() {
+        req = req.query(&[("maze", &maze)]);
+    }
+    let resp: LeaderboardResponse = req.send().await?.json().await?;
+
+    print_category("Fastest", &resp.fastest, |ms| {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    });
+    print_category("Candies", &resp.most_candies, |candies| candies.to_string());
+    print_category("Kills", &resp.most_kills, |kills| kills.to_string());
+
+    Ok(())
+}
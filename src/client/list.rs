@@ -13,7 +13,9 @@ pub async fn list_games(server: &str) -> Result<()> {
         Cell::new("Name"),
         Cell::new("Maze name"),
         Cell::new("Players"),
-        Cell::new("Finished"),
+        Cell::new("Mode"),
+        Cell::new("Phase"),
+        Cell::new("Seed"),
     ]));
     for game in resp.games {
         table.add_row(row!(
@@ -21,7 +23,9 @@ pub async fn list_games(server: &str) -> Result<()> {
             &game.name,
             &game.maze_name,
             &game.players.join(", "),
-            if game.finished { "Yes" } else { "No" }
+            &game.mode.to_string(),
+            &game.phase.to_string(),
+            &game.seed.to_string()
         ));
     }
     table.printstd();
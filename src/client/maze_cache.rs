@@ -0,0 +1,55 @@
+use anyhow::{anyhow, ensure, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::game::api::GetMazeResponse;
+use crate::game::map::Map;
+use crate::game::maze_loader::maze_hash;
+
+/// Mazes downloaded from servers, stored on disk by their content hash
+pub struct MazeCache {
+    dir: PathBuf,
+}
+
+impl Default for MazeCache {
+    fn default() -> Self {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        MazeCache {
+            dir: cache_home.join("candy_game").join("mazes"),
+        }
+    }
+}
+
+impl MazeCache {
+    /// Returns the maze with given hash, downloading it from the server if it's not cached yet
+    pub async fn get_or_fetch(&self, server: &str, maze_name: &str, hash: &str) -> Result<Map> {
+        let path = self.dir.join(hash);
+        if let Ok(template) = fs::read_to_string(&path) {
+            if maze_hash(&template) == hash {
+                return Map::from_template(&template);
+            }
+        }
+
+        let url = format!("http://{}/maze/{}", server, maze_name);
+        let resp = reqwest::get(&url).await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("Couldn't download maze: {}", resp.text().await?));
+        }
+        let maze: GetMazeResponse = resp.json().await?;
+        ensure!(
+            maze_hash(&maze.template) == hash,
+            "Maze {} on the server doesn't match the game's maze",
+            maze_name
+        );
+        let map = Map::from_template(&maze.template)?;
+
+        // a failing cache only means downloading the maze again next time
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(&path, &maze.template);
+        }
+        Ok(map)
+    }
+}
@@ -1,3 +1,13 @@
+pub mod account;
 pub mod create;
+pub mod events;
 pub mod join;
+pub mod leaderboard;
 pub mod list;
+pub mod maze_cache;
+pub mod renderer;
+pub mod replay;
+pub mod scoreboard;
+pub mod state;
+pub mod theme;
+pub mod viewport;
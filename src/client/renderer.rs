@@ -0,0 +1,349 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::io::{stdout, Stdout, Write};
+use std::sync::{Mutex, Once};
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::{cursor, screen, style};
+
+/// Used when the size of the terminal can't be found out, as with some serial consoles
+pub const DEFAULT_TERMINAL_SIZE: (u16, u16) = (80, 24);
+
+/// Raw mode of the terminal while a renderer owns it, kept out of the renderer so that a panic
+/// anywhere can restore the terminal before its message is printed
+static RAW_MODE: Mutex<Option<RawTerminal<Stdout>>> = Mutex::new(None);
+
+/// The 16 colors every color terminal has, the exact shades are up to the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+impl Color {
+    /// SGR parameter of the color as the text color. Termion writes even its named colors as
+    /// `38;5;n`, which terminals with only 16 colors don't understand.
+    fn fg_code(self) -> u8 {
+        // the colors are declared in the order of their ANSI numbers
+        let ansi = self as u8;
+        if ansi < 8 {
+            30 + ansi
+        } else {
+            90 + ansi - 8
+        }
+    }
+}
+
+/// How a cell is drawn besides its char
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    /// Color of the char, the terminal's own if missing
+    pub fg: Option<Color>,
+    /// Dimmed, for things remembered rather than seen
+    pub faint: bool,
+}
+
+impl Style {
+    fn write(&self, out: &mut impl Write) -> Result<()> {
+        write!(out, "{}", style::Reset)?;
+        if let Some(fg) = self.fg {
+            write!(out, "\x1b[{}m", fg.fg_code())?;
+        }
+        if self.faint {
+            write!(out, "{}", style::Faint)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    ch: char,
+    style: Style,
+}
+
+const BLANK: Cell = Cell {
+    ch: ' ',
+    style: Style {
+        fg: None,
+        faint: false,
+    },
+};
+
+/// Everything shown on the terminal at one moment, drawn in memory first. Positions are 1-based
+/// like `termion::cursor::Goto`, anything drawn outside of the frame is cut off.
+pub struct Frame {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    pub fn new((width, height): (u16, u16)) -> Self {
+        Frame {
+            width,
+            height,
+            cells: vec![BLANK; width as usize * height as usize],
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        let inside = (1..=self.width).contains(&x) && (1..=self.height).contains(&y);
+        inside.then(|| (y - 1) as usize * self.width as usize + (x - 1) as usize)
+    }
+
+    pub fn put(&mut self, x: u16, y: u16, ch: char, style: Style) {
+        if let Some(idx) = self.index(x, y) {
+            self.cells[idx] = Cell { ch, style };
+        }
+    }
+
+    /// Draws the text from the position on, every line of it below the previous one starting
+    /// in the same column
+    pub fn text(&mut self, x: u16, y: u16, text: &str) {
+        self.styled_text(x, y, text, Style::default());
+    }
+
+    pub fn styled_text(&mut self, x: u16, y: u16, text: &str, style: Style) {
+        // lines written for raw mode end with "\r\n", `lines` takes care of those too
+        for (i, line) in text.lines().enumerate() {
+            let y = y.saturating_add(i as u16);
+            for (j, ch) in line.chars().enumerate() {
+                self.put(x.saturating_add(j as u16), y, ch, style);
+            }
+        }
+    }
+
+    /// What has to be written to turn the `shown` frame on the screen into this one, the same
+    /// size, or a blank screen without it
+    fn changes_since(&self, shown: Option<&Frame>) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        if shown.is_none() {
+            write!(buf, "{}{}", style::Reset, termion::clear::All)?;
+        }
+
+        let mut style = Style::default();
+        let mut cursor = None;
+        for y in 1..=self.height {
+            for x in 1..=self.width {
+                let idx = self.index(x, y).expect("Cell inside of the frame");
+                let cell = self.cells[idx];
+                let unchanged = match shown {
+                    Some(shown) => shown.cells[idx] == cell,
+                    None => cell == BLANK,
+                };
+                if unchanged {
+                    continue;
+                }
+                if cursor != Some((x, y)) {
+                    write!(buf, "{}", cursor::Goto(x, y))?;
+                }
+                if cell.style != style {
+                    cell.style.write(&mut buf)?;
+                    style = cell.style;
+                }
+                write!(buf, "{}", cell.ch)?;
+                cursor = Some((x + 1, y));
+            }
+        }
+        if style != Style::default() {
+            write!(buf, "{}", style::Reset)?;
+        }
+        Ok(buf)
+    }
+}
+
+/// Owns the terminal while a game is shown. Frames are drawn on the alternate screen with the
+/// cursor hidden and only the cells changed since the previous frame are written, so nothing
+/// flickers even over slow connections. The terminal is restored once the renderer is dropped,
+/// also when panicking.
+pub struct Renderer {
+    out: Stdout,
+    /// What's on the screen, `None` before the first frame
+    shown: Option<Frame>,
+}
+
+impl Renderer {
+    pub fn new() -> Result<Self> {
+        restore_on_panic();
+        let raw = stdout().into_raw_mode()?;
+        *RAW_MODE.lock().unwrap_or_else(|err| err.into_inner()) = Some(raw);
+
+        let mut out = stdout();
+        write!(out, "{}{}", screen::ToAlternateScreen, cursor::Hide)?;
+        out.flush()?;
+        Ok(Renderer { out, shown: None })
+    }
+
+    /// Blank frame the size of the terminal, which may have been resized since the last one
+    pub fn frame(&self) -> Frame {
+        let size = termion::terminal_size()
+            .ok()
+            .filter(|&(width, height)| width > 0 && height > 0);
+        Frame::new(size.unwrap_or(DEFAULT_TERMINAL_SIZE))
+    }
+
+    /// Writes the cells that differ from the frame on the screen in one go
+    pub fn draw(&mut self, frame: Frame) -> Result<()> {
+        // a resized terminal may have rearranged anything, so it's drawn from scratch
+        let shown = self
+            .shown
+            .take()
+            .filter(|shown| shown.size() == frame.size());
+        let buf = frame.changes_since(shown.as_ref())?;
+
+        self.out.write_all(&buf)?;
+        self.out.flush()?;
+        self.shown = Some(frame);
+        Ok(())
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Back to the main screen with a visible cursor and out of raw mode
+fn restore_terminal() {
+    let mut out = stdout();
+    let _ = write!(
+        out,
+        "{}{}{}",
+        style::Reset,
+        cursor::Show,
+        screen::ToMainScreen
+    );
+    let _ = out.flush();
+    // dropping the raw terminal turns raw mode off
+    RAW_MODE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .take();
+}
+
+/// Restores the terminal before the panic message is printed, it would be lost on the alternate
+/// screen otherwise
+fn restore_on_panic() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let rendering = RAW_MODE
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .is_some();
+            if rendering {
+                restore_terminal();
+            }
+            default_hook(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escapes(style: Style) -> String {
+        let mut out = Vec::new();
+        style.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn changes(frame: &Frame, shown: Option<&Frame>) -> String {
+        String::from_utf8(frame.changes_since(shown).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn only_changed_cells_are_written() {
+        let mut shown = Frame::new((10, 3));
+        shown.text(1, 1, "abc");
+        let mut frame = Frame::new((10, 3));
+        frame.text(1, 1, "abd");
+        frame.text(5, 2, "xy");
+
+        let expected = format!("{}d{}xy", cursor::Goto(3, 1), cursor::Goto(5, 2));
+        assert_eq!(changes(&frame, Some(&shown)), expected);
+        assert_eq!(changes(&shown, Some(&shown)), "");
+    }
+
+    #[test]
+    fn first_frames_clear_the_screen_and_skip_blanks() {
+        let mut frame = Frame::new((10, 3));
+        frame.text(2, 3, "a");
+        let expected = format!(
+            "{}{}{}a",
+            style::Reset,
+            termion::clear::All,
+            cursor::Goto(2, 3)
+        );
+        assert_eq!(changes(&frame, None), expected);
+    }
+
+    #[test]
+    fn styles_are_switched_only_when_they_change_and_reset_at_the_end() {
+        let red = Style {
+            fg: Some(Color::Red),
+            faint: false,
+        };
+        let mut frame = Frame::new((4, 1));
+        frame.styled_text(1, 1, "ab", red);
+        frame.text(3, 1, "c");
+
+        let expected = format!(
+            "{}{}ab{}c",
+            cursor::Goto(1, 1),
+            escapes(red),
+            escapes(Style::default())
+        );
+        assert_eq!(changes(&frame, Some(&Frame::new((4, 1)))), expected);
+
+        frame.put(4, 1, 'd', red);
+        let ending = format!("d{}", style::Reset);
+        assert!(changes(&frame, Some(&Frame::new((4, 1)))).ends_with(&ending));
+    }
+
+    #[test]
+    fn text_outside_of_the_frame_is_cut_off() {
+        let mut frame = Frame::new((3, 2));
+        frame.text(2, 2, "abc\r\nde");
+        let shown = Frame::new((3, 2));
+        assert_eq!(
+            changes(&frame, Some(&shown)),
+            format!("{}ab", cursor::Goto(2, 2))
+        );
+    }
+
+    #[test]
+    fn colors_use_the_basic_escapes() {
+        let red = Style {
+            fg: Some(Color::Red),
+            faint: false,
+        };
+        assert_eq!(escapes(red), format!("{}\x1b[31m", style::Reset));
+        assert_eq!(Color::Black.fg_code(), 30);
+        assert_eq!(Color::White.fg_code(), 37);
+        assert_eq!(Color::BrightBlack.fg_code(), 90);
+        assert_eq!(Color::BrightWhite.fg_code(), 97);
+    }
+}
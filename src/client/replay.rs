@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use std::io::stdin;
+use std::path::Path;
+use termion::event::Key;
+use termion::input::TermRead;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::Sender;
+use tokio::time::{sleep, Duration};
+
+use crate::client::events::describe;
+use crate::client::renderer::{Frame, Renderer};
+use crate::client::theme::{PlayerSlots, Theme};
+use crate::game::recording::Recording;
+use crate::game::world::World;
+
+/// How many ticks `b`/`f` jump
+const SEEK_TICKS: usize = 50;
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 16.0;
+
+enum ReplayControl {
+    TogglePause,
+    StepForward,
+    StepBack,
+    SeekForward,
+    SeekBack,
+    Faster,
+    Slower,
+    Quit,
+}
+
+fn read_controls(tx: Sender<ReplayControl>) -> Result<()> {
+    let mut keys = stdin().keys();
+
+    loop {
+        let key = keys.next().ok_or(anyhow!("No key pressed"))?;
+
+        let control = match key? {
+            Key::Esc | Key::Char('q') | Key::Ctrl('c') => ReplayControl::Quit,
+            Key::Char(' ') => ReplayControl::TogglePause,
+            Key::Right => ReplayControl::StepForward,
+            Key::Left => ReplayControl::StepBack,
+            Key::Char('f') => ReplayControl::SeekForward,
+            Key::Char('b') => ReplayControl::SeekBack,
+            Key::Char('+') => ReplayControl::Faster,
+            Key::Char('-') => ReplayControl::Slower,
+            _ => continue,
+        };
+        let quit = matches!(control, ReplayControl::Quit);
+        tx.blocking_send(control)?;
+        if quit {
+            return Ok(());
+        }
+    }
+}
+
+struct ReplayState {
+    recording: Recording,
+    world: World,
+    /// Number of recorded ticks applied to `world`
+    tick: usize,
+    paused: bool,
+    speed: f64,
+    player_slots: PlayerSlots,
+}
+
+impl ReplayState {
+    fn seek(&mut self, tick: usize) -> Result<()> {
+        let tick = tick.min(self.recording.ticks.len());
+        if tick < self.tick {
+            // the world can't step back, replay it from the start instead
+            self.world = self.recording.world_at(tick)?;
+        } else {
+            for record in &self.recording.ticks[self.tick..tick] {
+                self.world.replay_tick(record);
+            }
+        }
+        self.tick = tick;
+        self.player_slots.update(&self.world.scores());
+        Ok(())
+    }
+
+    fn game_time(&self, tick: usize) -> String {
+        let secs = tick as u64 * self.recording.tick_ms / 1000;
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+
+    fn draw(&self, frame: &mut Frame, theme: &Theme) {
+        let map = self.world.get_map();
+        theme.draw_map(frame, &map, &self.player_slots);
+
+        let logs = self.recording.ticks[..self.tick]
+            .iter()
+            .enumerate()
+            .flat_map(|(tick, record)| {
+                record
+                    .events
+                    .iter()
+                    .map(move |event| format!("{}: {}", self.game_time(tick + 1), describe(event)))
+            })
+            .collect::<Vec<String>>();
+        for (i, log) in logs.iter().rev().take(map.height()).enumerate() {
+            frame.text(map.width() as u16 + 2, 1 + i as u16, log);
+        }
+
+        let status = format!(
+            "tick {}/{} {} speed {}x{}\n\
+             space: pause, left/right: step, b/f: seek, +/-: speed, q: quit",
+            self.tick,
+            self.recording.ticks.len(),
+            self.game_time(self.tick),
+            self.speed,
+            if self.paused { " [paused]" } else { "" },
+        );
+        frame.text(1, map.height() as u16 + 1, &status);
+    }
+}
+
+/// Plays a recorded game, re-simulating it from the recorded seed and inputs
+pub async fn replay(path: &Path, speed: f64, theme: &Theme) -> Result<()> {
+    let recording = Recording::load(path)?;
+    let world = recording.world_at(0)?;
+    let mut state = ReplayState {
+        recording,
+        world,
+        tick: 0,
+        paused: false,
+        speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+        player_slots: PlayerSlots::default(),
+    };
+    state.seek(0)?;
+
+    let mut renderer = Renderer::new()?;
+    let (tx, mut rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let _ = read_controls(tx);
+    });
+
+    loop {
+        let mut frame = renderer.frame();
+        state.draw(&mut frame, theme);
+        renderer.draw(frame)?;
+
+        let finished = state.tick == state.recording.ticks.len();
+        let frame_time =
+            Duration::from_secs_f64(state.recording.tick_ms as f64 / 1000.0 / state.speed);
+
+        tokio::select! {
+            control = rx.recv() => match control {
+                Some(ReplayControl::TogglePause) => state.paused = !state.paused,
+                Some(ReplayControl::StepForward) => {
+                    state.paused = true;
+                    state.seek(state.tick + 1)?;
+                }
+                Some(ReplayControl::StepBack) => {
+                    state.paused = true;
+                    state.seek(state.tick.saturating_sub(1))?;
+                }
+                Some(ReplayControl::SeekForward) => state.seek(state.tick + SEEK_TICKS)?,
+                Some(ReplayControl::SeekBack) => state.seek(state.tick.saturating_sub(SEEK_TICKS))?,
+                Some(ReplayControl::Faster) => state.speed = (state.speed * 2.0).min(MAX_SPEED),
+                Some(ReplayControl::Slower) => state.speed = (state.speed / 2.0).max(MIN_SPEED),
+                Some(ReplayControl::Quit) | None => break,
+            },
+            _ = sleep(frame_time), if !state.paused && !finished => state.seek(state.tick + 1)?,
+        }
+    }
+    Ok(())
+}
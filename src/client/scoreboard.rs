@@ -0,0 +1,96 @@
+use prettytable::{row, Table};
+
+use crate::client::renderer::{Frame, Style};
+use crate::client::theme::{PlayerSlots, Theme};
+use crate::game::api::PlayerScore;
+
+fn format_team(score: &PlayerScore) -> String {
+    match score.team {
+        Some(team) => format!(" [{}]", team),
+        None => String::new(),
+    }
+}
+
+fn format_exit_time(exit_ticks: Option<u64>, tick_ms: u64) -> String {
+    match exit_ticks {
+        Some(ticks) => format!("{:.1}s", (ticks * tick_ms) as f64 / 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Draws the scores in a column starting at `x` with `player_id` marked and the names in the
+/// players' colors, returns how many lines were used
+pub fn draw_score_panel(
+    frame: &mut Frame,
+    x: u16,
+    scores: &[PlayerScore],
+    player_id: Option<u64>,
+    theme: &Theme,
+    slots: &PlayerSlots,
+) -> u16 {
+    frame.text(x, 1, "SCORE");
+    for (i, score) in scores.iter().enumerate() {
+        let marker = if Some(score.player_id) == player_id {
+            '>'
+        } else {
+            ' '
+        };
+        let status = if score.dead {
+            " (dead)"
+        } else if score.exit_ticks.is_some() {
+            " (out)"
+        } else {
+            ""
+        };
+        let y = 2 + i as u16;
+        let name = format!("{:<20}", score.name);
+        let style = theme.player_style(slots.get(score.player_id));
+        frame.put(x, y, marker, Style::default());
+        frame.styled_text(x + 1, y, &name, style);
+        let line = format!(" {:>6}{}{}", score.points, format_team(score), status);
+        frame.text(x + 1 + name.chars().count() as u16, y, &line);
+    }
+    1 + scores.len() as u16
+}
+
+/// Final scores of a finished game, drawn over the whole frame, `player_id` is `None` for
+/// spectators
+pub fn draw_scoreboard(
+    frame: &mut Frame,
+    title: &str,
+    scores: &[PlayerScore],
+    player_id: Option<u64>,
+    tick_ms: u64,
+) {
+    let mut table = Table::new();
+    table.set_titles(row![
+        "#", "Player", "Candies", "Mobs", "Kills", "Deaths", "Exit", "Points"
+    ]);
+    for (i, score) in scores.iter().enumerate() {
+        let you = if Some(score.player_id) == player_id {
+            " (you)"
+        } else {
+            ""
+        };
+        let name = format!("{}{}{}", score.name, format_team(score), you);
+        table.add_row(row![
+            i + 1,
+            name,
+            score.stats.candies,
+            score.stats.mob_kills,
+            score.stats.player_kills,
+            score.stats.deaths,
+            format_exit_time(score.exit_ticks, tick_ms),
+            r->score.points
+        ]);
+    }
+
+    let hint = match player_id {
+        Some(_) => "Press p to play again, q to leave",
+        None => "Press q to leave",
+    };
+    let table = table.to_string();
+    frame.text(1, 1, title);
+    frame.text(1, 3, &table);
+    frame.text(1, 4 + table.lines().count() as u16, hint);
+}
@@ -0,0 +1,184 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::client::theme::PlayerSlots;
+use crate::game::api::{GetStateResponse, ObjectsUpdate};
+use crate::game::event::LoggedEvent;
+use crate::game::map::{Map, ObjectType, Point};
+use crate::game::visibility::Sight;
+
+/// Client side copy of the world, built up from state updates sent by the server
+#[derive(Default)]
+pub struct GameView {
+    tick: Option<u64>,
+    objects: HashMap<u64, (ObjectType, Point)>,
+    events: Vec<LoggedEvent>,
+    /// Player whose sight the updates are limited to
+    player_id: Option<u64>,
+    /// Cells seen at the last update, `None` if the whole maze is in sight
+    visible: Option<HashSet<Point>>,
+    /// Cells seen at some point, shown dimmed when out of sight
+    explored: HashSet<Point>,
+    player_slots: PlayerSlots,
+}
+
+impl GameView {
+    /// Tick of the last applied update, to be sent back to the server
+    pub fn tick(&self) -> Option<u64> {
+        self.tick
+    }
+
+    pub fn apply(&mut self, state: &GetStateResponse) {
+        match &state.objects {
+            ObjectsUpdate::Snapshot { objects } => {
+                self.objects = objects
+                    .iter()
+                    .map(|(id, type_, pos)| (*id, (*type_, *pos)))
+                    .collect();
+                self.events.clear();
+            }
+            ObjectsUpdate::Delta { changed, removed } => {
+                for (id, type_, pos) in changed {
+                    self.objects.insert(*id, (*type_, *pos));
+                }
+                for id in removed {
+                    self.objects.remove(id);
+                }
+            }
+        }
+        self.events.extend(state.events.iter().cloned());
+        self.player_slots.update(&state.scores);
+        self.tick = Some(state.tick);
+    }
+
+    /// Remembers what the player sees of the `map`, a spectator following somebody else starts
+    /// with a blank maze
+    pub fn look(&mut self, map: &Map, state: &GetStateResponse) {
+        if state.player_id != self.player_id {
+            self.player_id = state.player_id;
+            self.explored.clear();
+        }
+        self.visible = match state.sight {
+            Sight::Everything => None,
+            sight => {
+                let visible = sight.visible_cells(map);
+                self.explored.extend(visible.iter().copied());
+                Some(visible)
+            }
+        };
+    }
+
+    pub fn visible(&self) -> Option<&HashSet<Point>> {
+        self.visible.as_ref()
+    }
+
+    pub fn explored(&self) -> &HashSet<Point> {
+        &self.explored
+    }
+
+    /// The `map` with the objects in it, players can be told apart by their ids
+    pub fn place_objects(&self, mut map: Map) -> Map {
+        for (id, (type_, pos)) in &self.objects {
+            map = map.place_object_with_id(*id, *type_, pos);
+        }
+        map
+    }
+
+    /// Where the object is, `None` if it isn't in the maze or in sight
+    pub fn position(&self, id: u64) -> Option<Point> {
+        self.objects.get(&id).map(|(_, pos)| *pos)
+    }
+
+    pub fn player_slots(&self) -> &PlayerSlots {
+        &self.player_slots
+    }
+
+    pub fn events(&self) -> &[LoggedEvent] {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::api::GamePhase;
+    use crate::game::visibility::Visibility;
+
+    fn response(player_id: Option<u64>, sight: Sight, objects: ObjectsUpdate) -> GetStateResponse {
+        GetStateResponse {
+            player_id,
+            phase: GamePhase::Running,
+            starts_in_ms: None,
+            ready: Vec::new(),
+            tick: 1,
+            objects,
+            is_finished: false,
+            player_winner: false,
+            player_dead: false,
+            lives: 1,
+            respawn_in_ms: None,
+            effects: Vec::new(),
+            keys: 0,
+            sight,
+            events: Vec::new(),
+            scores: Vec::new(),
+        }
+    }
+
+    fn seen_from(x: usize) -> Sight {
+        Sight::Around {
+            from: Point::new(x, 1),
+            visibility: Visibility {
+                radius: Some(1),
+                line_of_sight: false,
+            },
+        }
+    }
+
+    fn no_objects() -> ObjectsUpdate {
+        ObjectsUpdate::Delta {
+            changed: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn deltas_apply_on_top_of_the_snapshot() {
+        let mut view = GameView::default();
+        let snapshot = ObjectsUpdate::Snapshot {
+            objects: vec![
+                (1, ObjectType::Mob, Point::new(1, 1)),
+                (2, ObjectType::Candy, Point::new(2, 1)),
+            ],
+        };
+        view.apply(&response(None, Sight::Everything, snapshot));
+        let delta = ObjectsUpdate::Delta {
+            changed: vec![(1, ObjectType::Mob, Point::new(3, 1))],
+            removed: vec![2],
+        };
+        view.apply(&response(None, Sight::Everything, delta));
+
+        assert_eq!(view.position(1), Some(Point::new(3, 1)));
+        assert_eq!(view.position(2), None);
+        assert_eq!(view.tick(), Some(1));
+    }
+
+    #[test]
+    fn explored_cells_add_up_until_following_somebody_else() {
+        let map = Map::from_template("████████\n█      █\n████████").unwrap();
+        let mut view = GameView::default();
+
+        view.look(&map, &response(Some(1), seen_from(2), no_objects()));
+        view.look(&map, &response(Some(1), seen_from(5), no_objects()));
+        let visible = view.visible().unwrap();
+        assert!(visible.contains(&Point::new(5, 1)));
+        assert!(!visible.contains(&Point::new(2, 1)));
+        assert!(view.explored().contains(&Point::new(2, 1)));
+        assert!(view.explored().contains(&Point::new(5, 1)));
+
+        view.look(&map, &response(Some(2), seen_from(5), no_objects()));
+        assert!(!view.explored().contains(&Point::new(2, 1)));
+
+        view.look(&map, &response(Some(2), Sight::Everything, no_objects()));
+        assert!(view.visible().is_none());
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::client::renderer::{Color, Frame, Style};
+use crate::game::api::PlayerScore;
+use crate::game::map::{Map, ObjectType, Point};
+use crate::game::pickup::PickupKind;
+
+/// Names of the themes built into the client
+pub const BUILTIN_THEMES: [&str; 3] = ["default", "ascii", "mono"];
+
+/// What themes choose glyphs and colors for, object types regardless of the direction they face
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    Wall,
+    Player,
+    Shot,
+    Exit,
+    Mob,
+    Candy,
+    Speed,
+    Shield,
+    MultiShot,
+    Freeze,
+    Key,
+    Door,
+}
+
+impl Element {
+    fn of(type_: ObjectType) -> Option<Self> {
+        Some(match type_ {
+            ObjectType::Wall => Element::Wall,
+            ObjectType::Player(_) => Element::Player,
+            ObjectType::Shot(_) => Element::Shot,
+            ObjectType::Exit => Element::Exit,
+            ObjectType::Mob => Element::Mob,
+            ObjectType::Candy => Element::Candy,
+            ObjectType::Pickup(PickupKind::Speed) => Element::Speed,
+            ObjectType::Pickup(PickupKind::Shield) => Element::Shield,
+            ObjectType::Pickup(PickupKind::MultiShot) => Element::MultiShot,
+            ObjectType::Pickup(PickupKind::Freeze) => Element::Freeze,
+            ObjectType::Pickup(PickupKind::Key) => Element::Key,
+            ObjectType::Door => Element::Door,
+            ObjectType::Empty => return None,
+        })
+    }
+}
+
+/// Glyphs the objects are drawn with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GlyphSet {
+    /// The chars of the maze files, some terminal fonts lack a few of them
+    #[default]
+    Unicode,
+    /// Plain ASCII, shown right by any terminal
+    Ascii,
+}
+
+impl GlyphSet {
+    fn glyph(self, type_: ObjectType) -> char {
+        if self == GlyphSet::Unicode {
+            return type_.to_char();
+        }
+        match type_ {
+            ObjectType::Wall => '#',
+            ObjectType::Candy => 'o',
+            ObjectType::Pickup(PickupKind::Speed) => 'S',
+            ObjectType::Pickup(PickupKind::Shield) => 'H',
+            ObjectType::Pickup(PickupKind::MultiShot) => 'M',
+            ObjectType::Pickup(PickupKind::Freeze) => 'F',
+            ObjectType::Pickup(PickupKind::Key) => 'k',
+            ObjectType::Door => '+',
+            // the rest of the maze chars are ASCII already
+            _ => type_.to_char(),
+        }
+    }
+}
+
+/// Looks of the game. Theme files are JSON objects with any of the fields, the missing ones are
+/// taken from the default theme.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub glyph_set: GlyphSet,
+    /// Glyphs replacing those of the set, players and shots are always drawn facing their direction
+    pub glyphs: BTreeMap<Element, char>,
+    /// Elements without a color are drawn in the terminal's own
+    pub colors: BTreeMap<Element, Color>,
+    /// Players take turns in these colors to be told apart, with none they all get the color of
+    /// `player`
+    pub player_colors: Vec<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            glyph_set: GlyphSet::Unicode,
+            glyphs: BTreeMap::new(),
+            colors: BTreeMap::from([
+                (Element::Wall, Color::Blue),
+                (Element::Shot, Color::BrightYellow),
+                (Element::Exit, Color::Green),
+                (Element::Mob, Color::Red),
+                (Element::Candy, Color::BrightMagenta),
+                (Element::Speed, Color::Cyan),
+                (Element::Shield, Color::BrightBlue),
+                (Element::MultiShot, Color::BrightRed),
+                (Element::Freeze, Color::BrightCyan),
+                (Element::Key, Color::Yellow),
+                (Element::Door, Color::Yellow),
+            ]),
+            player_colors: vec![
+                Color::BrightGreen,
+                Color::BrightCyan,
+                Color::BrightYellow,
+                Color::BrightMagenta,
+                Color::BrightRed,
+                Color::BrightBlue,
+                Color::BrightWhite,
+                Color::Magenta,
+            ],
+        }
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Theme::default()),
+            "ascii" => Some(Theme {
+                glyph_set: GlyphSet::Ascii,
+                ..Theme::default()
+            }),
+            // how the game looked before it had colors
+            "mono" => Some(Theme {
+                glyph_set: GlyphSet::Unicode,
+                glyphs: BTreeMap::new(),
+                colors: BTreeMap::new(),
+                player_colors: Vec::new(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Loads a built-in theme, a theme file at the path or `<name>.json` from the user's theme
+    /// directory
+    pub fn load(name: &str) -> Result<Self> {
+        if let Some(theme) = Theme::builtin(name) {
+            return Ok(theme);
+        }
+        let path = Path::new(name);
+        let path = if path.is_file() {
+            path.to_path_buf()
+        } else {
+            themes_dir().join(format!("{}.json", name))
+        };
+        if !path.is_file() {
+            return Err(anyhow!(
+                "Unknown theme {}, pick one of {} or a theme file, also looked for {:?}",
+                name,
+                BUILTIN_THEMES.join(", "),
+                path
+            ));
+        }
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read theme file {:?}", path))?;
+        serde_json::from_str(&json).with_context(|| format!("Invalid theme file {:?}", path))
+    }
+
+    /// Theme used unless one is picked, ASCII on terminals that don't expect UTF-8
+    pub fn detect() -> Self {
+        if utf8_locale() {
+            Theme::default()
+        } else {
+            Theme::builtin("ascii").expect("Built-in ASCII theme")
+        }
+    }
+
+    /// Glyph and style of the object, players in the color of their slot
+    pub fn draw(&self, type_: ObjectType, player_slot: Option<usize>) -> (char, Style) {
+        let element = Element::of(type_);
+        let glyph = match type_ {
+            ObjectType::Player(_) | ObjectType::Shot(_) => type_.to_char(),
+            _ => element
+                .and_then(|element| self.glyphs.get(&element).copied())
+                .unwrap_or_else(|| self.glyph_set.glyph(type_)),
+        };
+        let style = match type_ {
+            ObjectType::Player(_) => self.player_style(player_slot),
+            _ => Style {
+                fg: element.and_then(|element| self.colors.get(&element).copied()),
+                faint: false,
+            },
+        };
+        (glyph, style)
+    }
+
+    /// Style of the player in the slot, also used for their name
+    pub fn player_style(&self, player_slot: Option<usize>) -> Style {
+        let color = match player_slot {
+            Some(slot) if !self.player_colors.is_empty() => {
+                Some(self.player_colors[slot % self.player_colors.len()])
+            }
+            _ => self.colors.get(&Element::Player).copied(),
+        };
+        Style {
+            fg: color,
+            faint: false,
+        }
+    }
+
+    /// Draws the whole maze from the top left corner of the frame
+    pub fn draw_map(&self, frame: &mut Frame, map: &Map, slots: &PlayerSlots) {
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                let object = map.get_object(&Point::new(x, y));
+                let (glyph, style) = self.draw(object.type_, slots.get(object.id));
+                frame.put(x as u16 + 1, y as u16 + 1, glyph, style);
+            }
+        }
+    }
+}
+
+/// Which of the theme's player colors every player has, kept for as long as they stay in the game
+#[derive(Default)]
+pub struct PlayerSlots {
+    slots: BTreeMap<u64, usize>,
+}
+
+impl PlayerSlots {
+    /// Frees the slots of the players who left, new players take the lowest free ones in the order
+    /// of their names so that everybody watching the game sees the same colors
+    pub fn update(&mut self, scores: &[PlayerScore]) {
+        self.slots
+            .retain(|id, _| scores.iter().any(|score| score.player_id == *id));
+        let mut joined: Vec<&PlayerScore> = scores
+            .iter()
+            .filter(|score| !self.slots.contains_key(&score.player_id))
+            .collect();
+        joined.sort_by(|a, b| a.name.cmp(&b.name));
+        for score in joined {
+            let slot = (0..)
+                .find(|slot| !self.slots.values().any(|taken| taken == slot))
+                .expect("Free slot");
+            self.slots.insert(score.player_id, slot);
+        }
+    }
+
+    pub fn get(&self, player_id: u64) -> Option<usize> {
+        self.slots.get(&player_id).copied()
+    }
+}
+
+fn themes_dir() -> PathBuf {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(std::env::temp_dir);
+    config_home.join("candy_game").join("themes")
+}
+
+/// Whether the locale says the terminal takes UTF-8, terminals without any locale set are
+/// trusted to
+fn utf8_locale() -> bool {
+    // the first one set wins, like in the C library
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()));
+    locale.is_none_or(|locale| {
+        let locale = locale.to_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores(players: &[(u64, &str)]) -> Vec<PlayerScore> {
+        players
+            .iter()
+            .map(|(player_id, name)| PlayerScore {
+                player_id: *player_id,
+                name: name.to_string(),
+                stats: Default::default(),
+                exit_ticks: None,
+                dead: false,
+                team: None,
+                points: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn new_players_take_the_free_slots_by_name() {
+        let mut slots = PlayerSlots::default();
+        slots.update(&scores(&[(7, "carol"), (3, "alice"), (5, "bob")]));
+        assert_eq!(slots.get(3), Some(0));
+        assert_eq!(slots.get(5), Some(1));
+        assert_eq!(slots.get(7), Some(2));
+        assert_eq!(slots.get(1), None);
+    }
+
+    #[test]
+    fn players_keep_their_slots_and_leave_them_to_newcomers() {
+        let mut slots = PlayerSlots::default();
+        slots.update(&scores(&[(3, "alice"), (5, "bob"), (7, "carol")]));
+        slots.update(&scores(&[(7, "carol"), (3, "alice")]));
+        assert_eq!(slots.get(5), None);
+        assert_eq!(slots.get(7), Some(2));
+
+        slots.update(&scores(&[(9, "aaron"), (7, "carol"), (3, "alice")]));
+        assert_eq!(slots.get(9), Some(1));
+        assert_eq!(slots.get(3), Some(0));
+    }
+}
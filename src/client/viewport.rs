@@ -0,0 +1,116 @@
+use crate::game::map::{Map, Point};
+
+/// Columns right of the maze kept for the scores and events
+const PANEL_WIDTH: usize = 44;
+/// Rows below the maze kept for the status line and the cursor
+const HUD_HEIGHT: usize = 2;
+/// The maze gives up columns to the panel, but never gets narrower than this
+const MIN_WIDTH: usize = 20;
+
+/// The part of the maze shown on the terminal, scrolled to keep a point of interest in the middle
+#[derive(Default)]
+pub struct Viewport {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Viewport {
+    /// Fits the viewport into a terminal of `size` columns and rows, next to the panel and above
+    /// the status line, and scrolls it to `focus` as far as the maze goes. Without a focus it
+    /// stays where it is.
+    pub fn update(&mut self, map: &Map, size: (u16, u16), focus: Option<Point>) {
+        let (columns, rows) = (size.0 as usize, size.1 as usize);
+        self.width = map
+            .width()
+            .min(columns.saturating_sub(PANEL_WIDTH + 1).max(MIN_WIDTH));
+        self.height = map.height().min(rows.saturating_sub(HUD_HEIGHT).max(1));
+        if let Some(focus) = focus {
+            self.x = focus.x.saturating_sub(self.width / 2);
+            self.y = focus.y.saturating_sub(self.height / 2);
+        }
+        self.x = self.x.min(map.width() - self.width);
+        self.y = self.y.min(map.height() - self.height);
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Column the panel starts at, 1-based like `termion::cursor::Goto`
+    pub fn panel_x(&self) -> u16 {
+        self.width as u16 + 2
+    }
+
+    /// Maze cell in the middle of the viewport
+    pub fn center(&self) -> Point {
+        Point::new(self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Maze cells in every row of the viewport, top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = Point>> + '_ {
+        (self.y..self.y + self.height)
+            .map(move |y| (self.x..self.x + self.width).map(move |x| Point::new(x, y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maze(width: usize, height: usize) -> Map {
+        let wall = "█".repeat(width);
+        let row = format!("█{}█", " ".repeat(width - 2));
+        let mut rows = vec![wall.clone()];
+        rows.extend(std::iter::repeat_n(row, height - 2));
+        rows.push(wall);
+        Map::from_template(&rows.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn small_mazes_are_shown_whole() {
+        let map = maze(10, 5);
+        let mut viewport = Viewport::default();
+        viewport.update(&map, (80, 24), Some(Point::new(8, 3)));
+        assert_eq!((viewport.width(), viewport.height()), (10, 5));
+        assert_eq!(viewport.center(), Point::new(5, 2));
+        assert_eq!(viewport.panel_x(), 12);
+    }
+
+    #[test]
+    fn large_mazes_scroll_to_the_focus_within_their_bounds() {
+        let map = maze(100, 50);
+        let mut viewport = Viewport::default();
+        viewport.update(&map, (80, 24), Some(Point::new(50, 25)));
+        assert_eq!((viewport.width(), viewport.height()), (35, 22));
+        assert_eq!(viewport.center(), Point::new(50, 25));
+
+        viewport.update(&map, (80, 24), Some(Point::new(99, 49)));
+        let last_row: Vec<Point> = viewport.rows().last().unwrap().collect();
+        assert_eq!(last_row.last(), Some(&Point::new(99, 49)));
+
+        // without a focus it stays put
+        viewport.update(&map, (80, 24), None);
+        assert_eq!(
+            viewport.rows().last().unwrap().last(),
+            Some(Point::new(99, 49))
+        );
+    }
+
+    #[test]
+    fn narrow_terminals_keep_a_minimum_maze_width() {
+        let map = maze(100, 50);
+        let mut viewport = Viewport::default();
+        viewport.update(&map, (40, 10), Some(Point::new(0, 0)));
+        assert_eq!((viewport.width(), viewport.height()), (MIN_WIDTH, 8));
+        assert_eq!(
+            viewport.rows().next().unwrap().next(),
+            Some(Point::new(0, 0))
+        );
+    }
+}
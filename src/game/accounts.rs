@@ -0,0 +1,258 @@
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::Local;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::game::api::{PlayerProfile, PlayerStats};
+use crate::game::session::{hash_token, new_token};
+
+const MAX_NAME_LEN: usize = 20;
+const MIN_PASSWORD_LEN: usize = 6;
+/// Login tokens expire this long after the login
+const LOGIN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Serialize, Deserialize)]
+struct Account {
+    /// Argon2 hash in the PHC string format
+    password_hash: String,
+    registered: String,
+    stats: PlayerStats,
+}
+
+/// Registered players, persisted as a JSON file if created with a path. Changes are written by
+/// whoever takes them with `unsaved`, so that file writes don't happen under the accounts' lock.
+pub struct Accounts {
+    path: Option<PathBuf>,
+    accounts: BTreeMap<String, Account>,
+    /// Account names and login times by login token hash, logins don't survive restarts
+    sessions: HashMap<String, (String, Instant)>,
+    /// Whether the accounts changed since they were last taken by `unsaved`
+    changed: bool,
+}
+
+/// Checks the name and password of a new account, whether the name is free aside
+pub fn validate(name: &str, password: &str) -> Result<()> {
+    ensure!(
+        !name.trim().is_empty() && name.chars().count() <= MAX_NAME_LEN,
+        "Name has to have 1 to {} characters",
+        MAX_NAME_LEN
+    );
+    ensure!(
+        password.chars().count() >= MIN_PASSWORD_LEN,
+        "Password has to have at least {} characters",
+        MIN_PASSWORD_LEN
+    );
+    Ok(())
+}
+
+/// Argon2 hash of the password in the PHC string format. Hashing takes a while on purpose, so
+/// the server runs it on a blocking thread.
+pub fn hash_password(password: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|err| anyhow!("{}", err))?;
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow!("Couldn't hash password: {}", err))?
+        .to_string())
+}
+
+/// Whether the password matches the hash, as slow as `hash_password`
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+impl Accounts {
+    pub fn in_memory() -> Self {
+        Accounts {
+            path: None,
+            accounts: BTreeMap::new(),
+            sessions: HashMap::new(),
+            changed: false,
+        }
+    }
+
+    /// Loads the accounts from `path`, starting with none if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let accounts = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid accounts file {:?}", path))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err).with_context(|| format!("Couldn't read {:?}", path)),
+        };
+        Ok(Accounts {
+            path: Some(path.to_path_buf()),
+            accounts,
+            sessions: HashMap::new(),
+            changed: false,
+        })
+    }
+
+    /// The file and its new contents if the accounts changed since the last call
+    pub fn unsaved(&mut self) -> Result<Option<(PathBuf, Vec<u8>)>> {
+        let Some(path) = self.path.as_ref().filter(|_| self.changed) else {
+            return Ok(None);
+        };
+        let bytes = serde_json::to_vec_pretty(&self.accounts)?;
+        self.changed = false;
+        Ok(Some((path.clone(), bytes)))
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.accounts.contains_key(name)
+    }
+
+    /// Adds the account with the password hashed by `hash_password`, the name may have been
+    /// taken while hashing
+    pub fn register(&mut self, name: &str, password_hash: String) -> Result<()> {
+        if self.is_registered(name) {
+            bail!("Name {} is already taken", name);
+        }
+        self.accounts.insert(
+            name.to_string(),
+            Account {
+                password_hash,
+                registered: Local::now().format("%Y-%m-%d").to_string(),
+                stats: PlayerStats::default(),
+            },
+        );
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Hash to check the account's password against with `verify_password`
+    pub fn password_hash(&self, name: &str) -> Option<String> {
+        self.accounts
+            .get(name)
+            .map(|account| account.password_hash.clone())
+    }
+
+    /// New login token for the account whose password was verified
+    pub fn login(&mut self, name: &str) -> String {
+        let token = new_token();
+        self.sessions
+            .insert(hash_token(&token), (name.to_string(), Instant::now()));
+        token
+    }
+
+    /// Name of the account the login token belongs to, if it didn't expire
+    pub fn authenticate(&self, token: &str) -> Option<&str> {
+        self.sessions
+            .get(&hash_token(token))
+            .filter(|(_, logged_in)| logged_in.elapsed() < LOGIN_TTL)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Forgets the expired login tokens
+    pub fn remove_expired_logins(&mut self) {
+        self.sessions
+            .retain(|_, (_, logged_in)| logged_in.elapsed() < LOGIN_TTL);
+    }
+
+    pub fn profile(&self, name: &str) -> Option<PlayerProfile> {
+        self.accounts.get(name).map(|account| PlayerProfile {
+            name: name.to_string(),
+            registered: account.registered.clone(),
+            stats: account.stats,
+        })
+    }
+
+    /// Adds the stats of a finished or left game to the account
+    pub fn add_stats(&mut self, name: &str, stats: &PlayerStats) -> Result<()> {
+        let account = self
+            .accounts
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("Unknown account {}", name))?;
+        account.stats.add(stats);
+        self.changed = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_names_and_passwords() {
+        assert!(validate("alice", "secret").is_ok());
+        assert!(validate(" ", "secret").is_err());
+        assert!(validate(&"a".repeat(MAX_NAME_LEN + 1), "secret").is_err());
+        assert!(validate("alice", "short").is_err());
+    }
+
+    #[test]
+    fn verifies_hashed_passwords() {
+        let password_hash = hash_password("secret").unwrap();
+        assert!(verify_password("secret", &password_hash));
+        assert!(!verify_password("Secret", &password_hash));
+        assert!(!verify_password("secret", "not a hash"));
+    }
+
+    #[test]
+    fn names_are_registered_once() {
+        let mut accounts = Accounts::in_memory();
+        accounts.register("alice", "hash".to_string()).unwrap();
+        assert!(accounts.is_registered("alice"));
+        assert!(accounts.register("alice", "other".to_string()).is_err());
+        assert_eq!(accounts.password_hash("alice").as_deref(), Some("hash"));
+        assert_eq!(accounts.password_hash("bob"), None);
+    }
+
+    #[test]
+    fn logins_authenticate_their_account() {
+        let mut accounts = Accounts::in_memory();
+        accounts.register("alice", "hash".to_string()).unwrap();
+        let token = accounts.login("alice");
+        assert_eq!(accounts.authenticate(&token), Some("alice"));
+        assert_eq!(accounts.authenticate("forged"), None);
+    }
+
+    #[test]
+    fn logins_expire() {
+        let mut accounts = Accounts::in_memory();
+        accounts.register("alice", "hash".to_string()).unwrap();
+        let token = accounts.login("alice");
+        let fresh = accounts.login("alice");
+        let Some(expired) = Instant::now().checked_sub(LOGIN_TTL) else {
+            return;
+        };
+        accounts.sessions.get_mut(&hash_token(&token)).unwrap().1 = expired;
+        assert_eq!(accounts.authenticate(&token), None);
+
+        accounts.remove_expired_logins();
+        assert_eq!(accounts.sessions.len(), 1);
+        assert_eq!(accounts.authenticate(&fresh), Some("alice"));
+    }
+
+    #[test]
+    fn changes_are_taken_once() {
+        let path = std::env::temp_dir().join(format!("accounts-{}.json", std::process::id()));
+        let mut accounts = Accounts::load(&path).unwrap();
+        assert!(accounts.unsaved().unwrap().is_none());
+
+        accounts.register("alice", "hash".to_string()).unwrap();
+        let (unsaved_path, _) = accounts.unsaved().unwrap().unwrap();
+        assert_eq!(unsaved_path, path);
+        assert!(accounts.unsaved().unwrap().is_none());
+
+        let stats = PlayerStats {
+            candies: 2,
+            ..PlayerStats::default()
+        };
+        accounts.add_stats("alice", &stats).unwrap();
+        assert!(accounts.unsaved().unwrap().is_some());
+        assert_eq!(accounts.profile("alice").unwrap().stats.candies, 2);
+        assert!(accounts.add_stats("bob", &stats).is_err());
+    }
+}
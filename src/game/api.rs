@@ -1,6 +1,13 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
+use crate::game::event::LoggedEvent;
 use crate::game::map::{Direction, ObjectType, Point};
+use crate::game::maze_gen::{MazeGenerator, MazeSpec};
+use crate::game::mob::{MobConfig, MobKind};
+use crate::game::pickup::{PickupConfig, PickupKind};
+use crate::game::rules::{GameMode, Team};
+use crate::game::visibility::{Sight, Visibility};
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
 pub struct GameInfo {
@@ -8,7 +15,36 @@ pub struct GameInfo {
     pub name: String,
     pub maze_name: String,
     pub players: Vec<String>,
+    pub mode: GameMode,
+    pub phase: GamePhase,
     pub finished: bool,
+    /// Together with the player inputs reproduces the game
+    pub seed: u64,
+}
+
+/// Games wait in the lobby until all players are ready, count down and run until the rules of
+/// their mode end them. Finished and abandoned games are archived after a while.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GamePhase {
+    Lobby,
+    Countdown,
+    Running,
+    Finished,
+    Archived,
+}
+
+impl fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GamePhase::Lobby => "lobby",
+            GamePhase::Countdown => "countdown",
+            GamePhase::Running => "running",
+            GamePhase::Finished => "finished",
+            GamePhase::Archived => "archived",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -16,12 +52,77 @@ pub struct GetGamesResponse {
     pub games: Vec<GameInfo>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MazeInfo {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetMazesResponse {
+    pub mazes: Vec<MazeInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetMazeResponse {
+    pub name: String,
+    pub hash: String,
+    pub template: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateGameRequest {
     pub name: String,
     pub maze_name: String,
     pub mob_cnt: usize,
     pub candy_cnt: usize,
+    /// Mobs by kind, `mob_cnt` random walkers are spawned if empty
+    #[serde(default)]
+    pub mobs: Vec<MobConfig>,
+    /// Generate a `width` x `height` maze instead of using `maze_name`
+    #[serde(default)]
+    pub generator: Option<MazeGenerator>,
+    #[serde(default)]
+    pub width: usize,
+    #[serde(default)]
+    pub height: usize,
+    /// Seed of the generated maze and the world simulation, picked by the server if missing
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Length of one world tick, the server default is used if missing
+    #[serde(default)]
+    pub tick_ms: Option<u64>,
+    /// How long to count down once all players are ready, the server default is used if missing
+    #[serde(default)]
+    pub countdown_s: Option<u64>,
+    /// Pickups by kind, none if empty
+    #[serde(default)]
+    pub pickups: Vec<PickupConfig>,
+    #[serde(default)]
+    pub mode: GameMode,
+    /// Lives of every player, the server default is used if missing
+    #[serde(default)]
+    pub lives: Option<u32>,
+    /// How long players wait to respawn after losing a life, the server default is used if missing
+    #[serde(default)]
+    pub respawn_s: Option<u64>,
+    /// Fog of war, players see the whole maze if missing
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// How long a deathmatch lasts, the server default is used if missing
+    #[serde(default)]
+    pub time_limit_s: Option<u64>,
+}
+
+impl CreateGameRequest {
+    pub fn mob_configs(&self) -> Vec<MobConfig> {
+        if self.mobs.is_empty() {
+            vec![MobConfig::new(MobKind::Walker, self.mob_cnt)]
+        } else {
+            self.mobs.clone()
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -37,11 +138,46 @@ pub struct JoinGameRequest {
 
 #[derive(Serialize, Deserialize)]
 pub struct JoinGameResponse {
+    pub game_id: u64,
     pub player_id: u64,
+    /// Session token, sent as `Authorization: Bearer <token>` with the player's requests
+    pub token: String,
     pub maze_name: String,
+    /// Content hash of the maze, unknown mazes are fetched from `/maze/{maze_name}`
+    pub maze_hash: String,
+    /// Set for generated mazes, clients rebuild the maze from it
+    #[serde(default)]
+    pub maze_spec: Option<MazeSpec>,
+    /// Length of a tick, to show how long things took in seconds
+    pub tick_ms: u64,
 }
 
 #[derive(Serialize, Deserialize)]
+pub struct SpectateRequest {
+    pub game_id: u64,
+}
+
+/// Spectators watch the game without a player, their session can't be used to play
+#[derive(Serialize, Deserialize)]
+pub struct SpectateResponse {
+    pub game_id: u64,
+    /// Session token, sent as `Authorization: Bearer <token>` with the spectator's requests
+    pub token: String,
+    pub maze_name: String,
+    pub maze_hash: String,
+    #[serde(default)]
+    pub maze_spec: Option<MazeSpec>,
+    pub tick_ms: u64,
+}
+
+/// Sent by spectators over `/ws` to choose the player whose state they get
+#[derive(Serialize, Deserialize)]
+pub struct FollowRequest {
+    /// `None` for the free camera
+    pub player_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum PlayerAction {
     Shoot,
@@ -50,22 +186,159 @@ pub enum PlayerAction {
 
 #[derive(Serialize, Deserialize)]
 pub struct ActionRequest {
-    pub game_id: u64,
-    pub player_id: u64,
     pub action: PlayerAction,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GetStateRequest {
-    pub game_id: u64,
-    pub player_id: u64,
+    /// Last tick the client has seen, the response then only contains changes
+    #[serde(default)]
+    pub since_tick: Option<u64>,
+    /// Player whose lives, effects and keys a spectator gets, ignored for players
+    #[serde(default)]
+    pub follow: Option<u64>,
 }
 
+/// Objects are keyed by their world id so deltas can be applied on top of each other
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ObjectsUpdate {
+    /// All objects, sent when the client is unknown or too far behind
+    Snapshot {
+        objects: Vec<(u64, ObjectType, Point)>,
+    },
+    /// Objects added or moved since the client's tick and ids of removed ones
+    Delta {
+        changed: Vec<(u64, ObjectType, Point)>,
+        removed: Vec<u64>,
+    },
+}
+
+/// Also pushed over `/ws` after every tick, the client sends `PlayerAction`s over the same socket,
+/// or `FollowRequest`s when spectating
 #[derive(Serialize, Deserialize)]
 pub struct GetStateResponse {
-    pub objects: Vec<(ObjectType, Point)>,
+    /// Player the fields about the player belong to, the followed one for spectators
+    pub player_id: Option<u64>,
+    pub phase: GamePhase,
+    /// Time left until the game starts, during the countdown
+    pub starts_in_ms: Option<u64>,
+    /// Players who are ready to start
+    pub ready: Vec<u64>,
+    pub tick: u64,
+    pub objects: ObjectsUpdate,
     pub is_finished: bool,
     pub player_winner: bool,
+    /// The player lost all their lives
     pub player_dead: bool,
-    pub logs: Vec<String>,
+    /// Lives the player has left, counting the one they are playing
+    pub lives: u32,
+    /// Time left until the player is back in the maze after losing a life
+    pub respawn_in_ms: Option<u64>,
+    /// Timed effects of the pickups the player collected
+    pub effects: Vec<ActiveEffect>,
+    /// Keys the player holds to open doors
+    pub keys: u32,
+    /// Part of the maze the player sees, `objects` only contains what's in it
+    pub sight: Sight,
+    /// Only events that happened after the client's tick
+    pub events: Vec<LoggedEvent>,
+    /// Players currently in the game, best first
+    pub scores: Vec<PlayerScore>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ActiveEffect {
+    pub kind: PickupKind,
+    pub remaining_ms: u64,
+}
+
+/// What a player achieved, either in a single game or summed over all games of an account
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct PlayerStats {
+    pub games_played: u32,
+    pub wins: u32,
+    pub deaths: u32,
+    pub candies: u32,
+    pub mob_kills: u32,
+    pub player_kills: u32,
+}
+
+impl PlayerStats {
+    pub fn add(&mut self, other: &PlayerStats) {
+        self.games_played += other.games_played;
+        self.wins += other.wins;
+        self.deaths += other.deaths;
+        self.candies += other.candies;
+        self.mob_kills += other.mob_kills;
+        self.player_kills += other.player_kills;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerScore {
+    pub player_id: u64,
+    pub name: String,
+    pub stats: PlayerStats,
+    /// Ticks from joining to reaching the exit, if the player got there
+    pub exit_ticks: Option<u64>,
+    pub dead: bool,
+    /// Only set in team modes
+    pub team: Option<Team>,
+    pub points: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AccountRequest {
+    pub name: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginResponse {
+    /// Account token, sent as `Authorization: Bearer <token>` to `/join` to play as the account
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub name: String,
+    pub registered: String,
+    pub stats: PlayerStats,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaderboardQuery {
+    /// Only results on this maze, all mazes if missing
+    pub maze: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LeaderboardEntry {
+    pub player: String,
+    pub maze_name: String,
+    /// Day the game was finished, `YYYY-MM-DD`
+    pub date: String,
+    pub value: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LeaderboardResponse {
+    pub maze: Option<String>,
+    /// Time it took to reach the exit in milliseconds, shortest first
+    pub fastest: Vec<LeaderboardEntry>,
+    pub most_candies: Vec<LeaderboardEntry>,
+    /// Mobs and players killed in a single game
+    pub most_kills: Vec<LeaderboardEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GetRecordingsResponse {
+    pub recordings: Vec<RecordingInfo>,
 }
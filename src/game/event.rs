@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::mob::MobKind;
+use crate::game::pickup::PickupKind;
+
+/// Player as referred to by events, the name is included so events can be shown on their own
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlayerRef {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Killer {
+    Mob {
+        kind: MobKind,
+    },
+    /// Shot at point-blank range
+    Player {
+        player: PlayerRef,
+    },
+    /// A shot flying across the maze, fired by `shooter` unless they left the game
+    Shot {
+        shooter: Option<PlayerRef>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    PlayerJoined {
+        player: PlayerRef,
+    },
+    PlayerLeft {
+        player: PlayerRef,
+    },
+    CandyCollected {
+        player: PlayerRef,
+        remaining: usize,
+    },
+    MobKilled {
+        kind: MobKind,
+        by: Killer,
+    },
+    PlayerKilled {
+        player: PlayerRef,
+        by: Killer,
+    },
+    /// The player is back in the maze with `lives` lives left
+    PlayerRespawned {
+        player: PlayerRef,
+        lives: u32,
+    },
+    PickupCollected {
+        player: PlayerRef,
+        kind: PickupKind,
+    },
+    /// A timed effect of a pickup wore off
+    EffectEnded {
+        player: PlayerRef,
+        kind: PickupKind,
+    },
+    /// The player used a key to open a door
+    DoorOpened {
+        player: PlayerRef,
+    },
+    /// The player reached the exit before collecting all candies
+    ExitBlocked {
+        player: PlayerRef,
+        remaining: usize,
+    },
+    /// The player left the maze through the exit with all candies collected
+    PlayerExited {
+        player: PlayerRef,
+    },
+    PlayerWon {
+        player: PlayerRef,
+    },
+}
+
+/// Event together with when it happened
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LoggedEvent {
+    pub tick: u64,
+    /// Server wall clock time, `HH:MM:SS`
+    pub time: String,
+    pub event: GameEvent,
+}
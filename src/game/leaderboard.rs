@@ -0,0 +1,211 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::game::api::{LeaderboardEntry, LeaderboardResponse, PlayerScore};
+
+/// How many best results are listed per category
+const LEADERBOARD_LEN: usize = 10;
+
+/// What a player achieved in a finished game
+#[derive(Serialize, Deserialize)]
+struct PlayerResult {
+    game_id: u64,
+    maze_name: String,
+    player: String,
+    date: String,
+    /// How long it took the player to reach the exit, if they did
+    completion_ms: Option<u64>,
+    candies: u32,
+    kills: u32,
+}
+
+/// Results of all finished games, persisted as a JSON file if created with a path. Like accounts,
+/// changes are written by whoever takes them with `unsaved`.
+pub struct Leaderboard {
+    path: Option<PathBuf>,
+    results: Vec<PlayerResult>,
+    /// Games with results, each is only recorded once
+    recorded: HashSet<u64>,
+    /// Whether results were added since they were last taken by `unsaved`
+    changed: bool,
+}
+
+impl Leaderboard {
+    pub fn in_memory() -> Self {
+        Leaderboard {
+            path: None,
+            results: Vec::new(),
+            recorded: HashSet::new(),
+            changed: false,
+        }
+    }
+
+    /// Loads the results from `path`, starting with none if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let results: Vec<PlayerResult> = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid leaderboard file {:?}", path))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err).with_context(|| format!("Couldn't read {:?}", path)),
+        };
+        Ok(Leaderboard {
+            path: Some(path.to_path_buf()),
+            recorded: results.iter().map(|result| result.game_id).collect(),
+            results,
+            changed: false,
+        })
+    }
+
+    /// The file and its new contents if results were added since the last call
+    pub fn unsaved(&mut self) -> Result<Option<(PathBuf, Vec<u8>)>> {
+        let Some(path) = self.path.as_ref().filter(|_| self.changed) else {
+            return Ok(None);
+        };
+        let bytes = serde_json::to_vec(&self.results)?;
+        self.changed = false;
+        Ok(Some((path.clone(), bytes)))
+    }
+
+    /// Adds the final scores of a finished game, games are only recorded once
+    pub fn record_game(
+        &mut self,
+        game_id: u64,
+        maze_name: &str,
+        tick_ms: u64,
+        scores: &[PlayerScore],
+    ) {
+        if !self.recorded.insert(game_id) {
+            return;
+        }
+        let date = Local::now().format("%Y-%m-%d").to_string();
+        for score in scores {
+            self.results.push(PlayerResult {
+                game_id,
+                maze_name: maze_name.to_string(),
+                player: score.name.clone(),
+                date: date.clone(),
+                completion_ms: score.exit_ticks.map(|ticks| ticks * tick_ms),
+                candies: score.stats.candies,
+                kills: score.stats.mob_kills + score.stats.player_kills,
+            });
+        }
+        self.changed = true;
+    }
+
+    /// Best results on the maze, or on all mazes if `maze` is `None`
+    pub fn best(&self, maze: Option<&str>) -> LeaderboardResponse {
+        let results: Vec<&PlayerResult> = self
+            .results
+            .iter()
+            .filter(|result| maze.is_none_or(|maze| result.maze_name == maze))
+            .collect();
+        LeaderboardResponse {
+            maze: maze.map(str::to_string),
+            fastest: top(&results, |result| result.completion_ms, false),
+            most_candies: top(&results, |result| Some(result.candies as u64), true),
+            most_kills: top(&results, |result| Some(result.kills as u64), true),
+        }
+    }
+}
+
+/// Best results by `value`, results without a value or with zero are left out.
+/// Ties go to the earlier result.
+fn top(
+    results: &[&PlayerResult],
+    value: impl Fn(&PlayerResult) -> Option<u64>,
+    highest_first: bool,
+) -> Vec<LeaderboardEntry> {
+    let mut entries: Vec<LeaderboardEntry> = results
+        .iter()
+        .filter_map(|result| {
+            let value = value(result).filter(|value| *value > 0)?;
+            Some(LeaderboardEntry {
+                player: result.player.clone(),
+                maze_name: result.maze_name.clone(),
+                date: result.date.clone(),
+                value,
+            })
+        })
+        .collect();
+    if highest_first {
+        entries.sort_by_key(|entry| Reverse(entry.value));
+    } else {
+        entries.sort_by_key(|entry| entry.value);
+    }
+    entries.truncate(LEADERBOARD_LEN);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::api::PlayerStats;
+    use crate::game::maze_gen::{MazeGenerator, MazeSpec};
+
+    fn score(name: &str, exit_ticks: Option<u64>, candies: u32) -> PlayerScore {
+        PlayerScore {
+            player_id: 1,
+            name: name.to_string(),
+            stats: PlayerStats {
+                candies,
+                ..PlayerStats::default()
+            },
+            exit_ticks,
+            dead: false,
+            team: None,
+            points: 0,
+        }
+    }
+
+    #[test]
+    fn games_are_recorded_once() {
+        let mut leaderboard = Leaderboard::in_memory();
+        leaderboard.record_game(1, "classic", 100, &[score("alice", Some(30), 2)]);
+        leaderboard.record_game(1, "classic", 100, &[score("alice", Some(30), 2)]);
+        let best = leaderboard.best(None);
+        assert_eq!(best.fastest.len(), 1);
+        assert_eq!(best.fastest[0].value, 3000);
+        assert_eq!(best.most_candies[0].value, 2);
+        assert!(best.most_kills.is_empty());
+    }
+
+    #[test]
+    fn generated_mazes_are_kept_apart_by_seed() {
+        let spec = |seed| MazeSpec {
+            generator: MazeGenerator::Backtracker,
+            width: 21,
+            height: 11,
+            seed,
+        };
+        let mut leaderboard = Leaderboard::in_memory();
+        leaderboard.record_game(1, &spec(1).name(), 100, &[score("alice", Some(10), 0)]);
+        leaderboard.record_game(2, &spec(2).name(), 100, &[score("bob", Some(20), 0)]);
+
+        let best = leaderboard.best(Some(&spec(2).name()));
+        assert_eq!(best.fastest.len(), 1);
+        assert_eq!(best.fastest[0].player, "bob");
+        assert_eq!(leaderboard.best(None).fastest.len(), 2);
+    }
+
+    #[test]
+    fn results_are_written_once_taken() {
+        let path = std::env::temp_dir().join(format!("leaderboard-{}.json", std::process::id()));
+        let mut leaderboard = Leaderboard::load(&path).unwrap();
+        assert!(leaderboard.unsaved().unwrap().is_none());
+        leaderboard.record_game(1, "classic", 100, &[score("alice", None, 1)]);
+        let (_, bytes) = leaderboard.unsaved().unwrap().unwrap();
+        assert!(leaderboard.unsaved().unwrap().is_none());
+
+        fs::write(&path, bytes).unwrap();
+        let mut loaded = Leaderboard::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        loaded.record_game(1, "classic", 100, &[score("alice", None, 1)]);
+        assert!(loaded.unsaved().unwrap().is_none());
+        assert_eq!(loaded.best(None).most_candies.len(), 1);
+    }
+}
@@ -1,7 +1,12 @@
+use anyhow::{anyhow, bail, Result};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::game::mazes::MAZES;
+use crate::game::maze_gen::MazeSpec;
+use crate::game::pickup::PickupKind;
+
+/// How many random points are tried before looking through the whole maze for an empty one
+const RANDOM_POINT_TRIES: usize = 100;
 
 #[derive(Copy, Clone, Default, Debug, PartialEq, Serialize, Deserialize, Eq, Hash)]
 #[serde(rename_all = "lowercase", tag = "direction")]
@@ -13,6 +18,16 @@ pub enum Direction {
     Right,
 }
 
+impl Direction {
+    /// The two directions perpendicular to this one
+    pub fn sides(self) -> [Direction; 2] {
+        match self {
+            Direction::Up | Direction::Down => [Direction::Left, Direction::Right],
+            Direction::Left | Direction::Right => [Direction::Up, Direction::Down],
+        }
+    }
+}
+
 #[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Eq, Hash)]
 pub struct Point {
     pub x: usize,
@@ -47,9 +62,13 @@ impl Point {
         self.x = new_pos.x;
         self.y = new_pos.y;
     }
+    /// Steps between the points ignoring walls
+    pub fn distance(&self, other: &Point) -> usize {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
+    }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ObjectType {
     Wall,
     Player(Direction),
@@ -57,12 +76,15 @@ pub enum ObjectType {
     Exit,
     Mob,
     Candy,
+    Pickup(PickupKind),
+    /// Opened with a key
+    Door,
     Empty,
 }
 
 impl ObjectType {
-    fn from_char(c: char) -> Self {
-        match c {
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
             '█' => ObjectType::Wall,
             '^' => ObjectType::Player(Direction::Up),
             'v' => ObjectType::Player(Direction::Down),
@@ -73,11 +95,17 @@ impl ObjectType {
             'X' => ObjectType::Exit,
             '*' => ObjectType::Mob,
             '⏾' => ObjectType::Candy,
+            '»' => ObjectType::Pickup(PickupKind::Speed),
+            '◊' => ObjectType::Pickup(PickupKind::Shield),
+            '≡' => ObjectType::Pickup(PickupKind::MultiShot),
+            '❄' => ObjectType::Pickup(PickupKind::Freeze),
+            '⚷' => ObjectType::Pickup(PickupKind::Key),
+            '▒' => ObjectType::Door,
             ' ' => ObjectType::Empty,
-            _ => panic!("Unknown character in map template: {}", c),
-        }
+            _ => return None,
+        })
     }
-    fn to_char(&self) -> char {
+    pub fn to_char(self) -> char {
         match self {
             ObjectType::Wall => '█',
             ObjectType::Player(Direction::Up) => '^',
@@ -91,6 +119,12 @@ impl ObjectType {
             ObjectType::Exit => 'X',
             ObjectType::Mob => '*',
             ObjectType::Candy => '⏾',
+            ObjectType::Pickup(PickupKind::Speed) => '»',
+            ObjectType::Pickup(PickupKind::Shield) => '◊',
+            ObjectType::Pickup(PickupKind::MultiShot) => '≡',
+            ObjectType::Pickup(PickupKind::Freeze) => '❄',
+            ObjectType::Pickup(PickupKind::Key) => '⚷',
+            ObjectType::Door => '▒',
             ObjectType::Empty => ' ',
         }
     }
@@ -116,22 +150,42 @@ pub struct Map {
 }
 
 impl Map {
-    pub fn new(maze_name: &str) -> Self {
-        let template = MAZES.get(maze_name).unwrap();
+    pub fn generate(spec: &MazeSpec) -> Self {
+        Map::from_template(&spec.generate()).expect("Generated mazes are well formed")
+    }
 
+    /// Parses a maze drawn with the characters of `ObjectType`, rows have to be equally long
+    pub fn from_template(template: &str) -> Result<Self> {
         let mut map = Vec::new();
         let mut width = 0;
         let mut height = 0;
-        for line in template.lines() {
-            let row: Vec<MapObject> = line
+        for (y, line) in template.lines().enumerate() {
+            let row = line
                 .chars()
-                .map(|ch| MapObject::new(ObjectType::from_char(ch)))
-                .collect();
+                .map(|ch| {
+                    ObjectType::from_char(ch)
+                        .map(MapObject::new)
+                        .ok_or_else(|| {
+                            anyhow!("Unknown character '{}' on line {} of the maze", ch, y + 1)
+                        })
+                })
+                .collect::<Result<Vec<MapObject>>>()?;
+            if y > 0 && row.len() != width {
+                bail!(
+                    "Line {} of the maze is {} characters long, expected {}",
+                    y + 1,
+                    row.len(),
+                    width
+                );
+            }
             width = row.len();
             map.push(row);
             height += 1;
         }
-        Map { map, width, height }
+        if width == 0 {
+            bail!("The maze is empty");
+        }
+        Ok(Map { map, width, height })
     }
 
     pub fn width(&self) -> usize {
@@ -142,15 +196,47 @@ impl Map {
         self.height
     }
 
-    pub fn random_empty_point(&self) -> Point {
-        let mut rng = rand::thread_rng();
-        let mut x = rng.gen_range(1..(self.width - 1));
-        let mut y = rng.gen_range(1..(self.height - 1));
-        while !matches!(self.map[y][x].type_, ObjectType::Empty) {
-            x = rng.gen_range(1..(self.width - 1));
-            y = rng.gen_range(1..(self.height - 1));
+    /// Random empty point inside the border, `None` if there is none
+    pub fn random_empty_point(&self, rng: &mut impl Rng) -> Option<Point> {
+        // narrower maps have no inside at all
+        if self.width < 3 || self.height < 3 {
+            return None;
+        }
+        for _ in 0..RANDOM_POINT_TRIES {
+            let x = rng.gen_range(1..(self.width - 1));
+            let y = rng.gen_range(1..(self.height - 1));
+            if self.map[y][x].type_ == ObjectType::Empty {
+                return Some(Point::new(x, y));
+            }
+        }
+        // an almost full maze, so the few empty points are looked for instead of guessed
+        let empty: Vec<Point> = (1..(self.height - 1))
+            .flat_map(|y| (1..(self.width - 1)).map(move |x| Point::new(x, y)))
+            .filter(|pos| self.get_object(pos).type_ == ObjectType::Empty)
+            .collect();
+        empty.choose(rng).copied()
+    }
+
+    /// Clears the doors and pickups drawn in the maze, returns what was where
+    pub fn take_items(&mut self) -> Vec<(ObjectType, Point)> {
+        let mut taken = Vec::new();
+        for (y, row) in self.map.iter_mut().enumerate() {
+            for (x, object) in row.iter_mut().enumerate() {
+                if matches!(object.type_, ObjectType::Door | ObjectType::Pickup(_)) {
+                    taken.push((object.type_, Point::new(x, y)));
+                    *object = MapObject::new(ObjectType::Empty);
+                }
+            }
         }
-        Point::new(x, y)
+        taken
+    }
+
+    pub fn count(&self, type_: ObjectType) -> usize {
+        self.map
+            .iter()
+            .flatten()
+            .filter(|object| object.type_ == type_)
+            .count()
     }
 
     pub fn format(&self) -> String {
@@ -196,3 +282,21 @@ impl Map {
         self.map[pos2.y][pos2.x] = tmp;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn maps_without_an_inside_have_no_empty_points() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for template in ["  ", "  \n  ", " ", "   \n   "] {
+            let map = Map::from_template(template).unwrap();
+            assert!(map.random_empty_point(&mut rng).is_none(), "{template:?}");
+        }
+        let map = Map::from_template("   \n   \n   ").unwrap();
+        assert_eq!(map.random_empty_point(&mut rng), Some(Point::new(1, 1)));
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{ensure, Result};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const WALL: char = '█';
+const EMPTY: char = ' ';
+const EXIT: char = 'X';
+
+const MIN_SIZE: usize = 5;
+/// Generated mazes are at most this many cells wide and high
+pub const MAX_SIZE: usize = 255;
+/// Chance of a pillar at every other cell of an arena
+const PILLAR_CHANCE: f64 = 0.3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MazeGenerator {
+    /// Long winding corridors carved by a randomized depth-first search
+    Backtracker,
+    /// Many short dead ends grown by randomized Prim's algorithm
+    Prim,
+    /// Open space with scattered single-cell pillars
+    Arena,
+}
+
+impl MazeGenerator {
+    pub const ALL: [MazeGenerator; 3] = [
+        MazeGenerator::Backtracker,
+        MazeGenerator::Prim,
+        MazeGenerator::Arena,
+    ];
+}
+
+impl fmt::Display for MazeGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MazeGenerator::Backtracker => "backtracker",
+            MazeGenerator::Prim => "prim",
+            MazeGenerator::Arena => "arena",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for MazeGenerator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MazeGenerator::ALL
+            .into_iter()
+            .find(|generator| generator.to_string() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown maze generator: {}", s))
+    }
+}
+
+/// Everything needed to rebuild a generated maze, the same spec always yields the same maze
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MazeSpec {
+    pub generator: MazeGenerator,
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+}
+
+impl MazeSpec {
+    /// Tells apart every generated maze, so that e.g. leaderboards keep them apart
+    pub fn name(&self) -> String {
+        format!(
+            "{} {}x{} #{}",
+            self.generator, self.width, self.height, self.seed
+        )
+    }
+
+    /// Checks the maze isn't larger than `MAX_SIZE`, larger ones are cut down when generated
+    pub fn check_size(&self) -> Result<()> {
+        ensure!(
+            self.width <= MAX_SIZE && self.height <= MAX_SIZE,
+            "Generated mazes can be at most {}x{}, got {}x{}",
+            MAX_SIZE,
+            MAX_SIZE,
+            self.width,
+            self.height
+        );
+        Ok(())
+    }
+
+    /// Generates the maze in the same text format as the static `MAZES`
+    pub fn generate(&self) -> String {
+        // corridors and walls alternate, so both dimensions have to be odd
+        let width = (self.width.clamp(MIN_SIZE, MAX_SIZE) - 1) | 1;
+        let height = (self.height.clamp(MIN_SIZE, MAX_SIZE) - 1) | 1;
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let mut grid = match self.generator {
+            MazeGenerator::Backtracker => backtracker(width, height, &mut rng),
+            MazeGenerator::Prim => prim(width, height, &mut rng),
+            MazeGenerator::Arena => arena(width, height, &mut rng),
+        };
+        grid[height - 2][width - 2] = EXIT;
+
+        grid.iter()
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+fn walled_grid(width: usize, height: usize) -> Vec<Vec<char>> {
+    vec![vec![WALL; width]; height]
+}
+
+/// Cells two steps away in each direction that are still inside the border
+fn neighbours(x: usize, y: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    if x > 2 {
+        cells.push((x - 2, y));
+    }
+    if x + 2 < width - 1 {
+        cells.push((x + 2, y));
+    }
+    if y > 2 {
+        cells.push((x, y - 2));
+    }
+    if y + 2 < height - 1 {
+        cells.push((x, y + 2));
+    }
+    cells
+}
+
+fn backtracker(width: usize, height: usize, rng: &mut impl Rng) -> Vec<Vec<char>> {
+    let mut grid = walled_grid(width, height);
+    grid[1][1] = EMPTY;
+    let mut stack = vec![(1, 1)];
+
+    while let Some(&(x, y)) = stack.last() {
+        let unvisited: Vec<(usize, usize)> = neighbours(x, y, width, height)
+            .into_iter()
+            .filter(|&(nx, ny)| grid[ny][nx] == WALL)
+            .collect();
+        match unvisited.choose(rng) {
+            Some(&(nx, ny)) => {
+                grid[(y + ny) / 2][(x + nx) / 2] = EMPTY;
+                grid[ny][nx] = EMPTY;
+                stack.push((nx, ny));
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+    grid
+}
+
+fn prim(width: usize, height: usize, rng: &mut impl Rng) -> Vec<Vec<char>> {
+    let mut grid = walled_grid(width, height);
+    grid[1][1] = EMPTY;
+    // pairs of (maze cell, frontier cell behind a wall)
+    let mut frontier: Vec<((usize, usize), (usize, usize))> = neighbours(1, 1, width, height)
+        .into_iter()
+        .map(|cell| ((1, 1), cell))
+        .collect();
+
+    while !frontier.is_empty() {
+        let ((x, y), (nx, ny)) = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+        if grid[ny][nx] != WALL {
+            continue;
+        }
+        grid[(y + ny) / 2][(x + nx) / 2] = EMPTY;
+        grid[ny][nx] = EMPTY;
+        for cell in neighbours(nx, ny, width, height) {
+            if grid[cell.1][cell.0] == WALL {
+                frontier.push(((nx, ny), cell));
+            }
+        }
+    }
+    grid
+}
+
+fn arena(width: usize, height: usize, rng: &mut impl Rng) -> Vec<Vec<char>> {
+    let mut grid = walled_grid(width, height);
+    for (y, row) in grid.iter_mut().enumerate().take(height - 1).skip(1) {
+        for (x, cell) in row.iter_mut().enumerate().take(width - 1).skip(1) {
+            // pillars only at even coordinates never touch each other, so the
+            // arena always stays connected
+            let pillar = x % 2 == 0 && y % 2 == 0 && rng.gen_bool(PILLAR_CHANCE);
+            *cell = if pillar { WALL } else { EMPTY };
+        }
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::maze_loader::validate;
+
+    fn spec(generator: MazeGenerator, seed: u64) -> MazeSpec {
+        MazeSpec {
+            generator,
+            width: 30,
+            height: 15,
+            seed,
+        }
+    }
+
+    #[test]
+    fn the_same_spec_yields_the_same_maze() {
+        for generator in MazeGenerator::ALL {
+            assert_eq!(spec(generator, 7).generate(), spec(generator, 7).generate());
+            assert_ne!(spec(generator, 7).generate(), spec(generator, 8).generate());
+        }
+    }
+
+    #[test]
+    fn generated_mazes_are_valid_with_odd_sizes() {
+        for generator in MazeGenerator::ALL {
+            let map = validate(&spec(generator, 1).generate()).unwrap();
+            assert_eq!((map.width(), map.height()), (29, 15));
+        }
+    }
+
+    #[test]
+    fn oversized_mazes_are_rejected_and_cut_down() {
+        let mut spec = spec(MazeGenerator::Arena, 1);
+        spec.width = MAX_SIZE + 10;
+        assert!(spec.check_size().is_err());
+        let map = validate(&spec.generate()).unwrap();
+        assert!(map.width() <= MAX_SIZE);
+    }
+}
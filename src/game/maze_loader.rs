@@ -0,0 +1,182 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::game::map::{Map, ObjectType, Point};
+use crate::game::mazes::MAZES;
+use crate::game::pickup::PickupKind;
+
+/// Maze templates available to a server or client, built-in ones plus those loaded from disk
+#[derive(Clone)]
+pub struct MazeLibrary {
+    mazes: BTreeMap<String, String>,
+}
+
+impl MazeLibrary {
+    pub fn builtin() -> Self {
+        MazeLibrary {
+            mazes: MAZES
+                .entries()
+                .map(|(name, template)| (name.to_string(), template.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Built-in mazes extended with every file in `dir`, named by the file stem.
+    /// Mazes from disk override built-in ones with the same name.
+    pub fn with_dir(dir: &Path) -> Result<Self> {
+        let mut library = MazeLibrary::builtin();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("Couldn't read maze dir {:?}", dir))?;
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| anyhow!("Invalid maze file name {:?}", path))?
+                .to_string();
+            let template = fs::read_to_string(&path)
+                .with_context(|| format!("Couldn't read maze file {:?}", path))?;
+            let template = template.trim_end_matches(['\r', '\n']).replace('\r', "");
+            validate(&template).with_context(|| format!("Invalid maze file {:?}", path))?;
+            library.mazes.insert(name, template);
+        }
+        Ok(library)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.mazes.keys().cloned().collect()
+    }
+
+    pub fn contains(&self, maze_name: &str) -> bool {
+        self.mazes.contains_key(maze_name)
+    }
+
+    pub fn get(&self, maze_name: &str) -> Option<&str> {
+        self.mazes.get(maze_name).map(String::as_str)
+    }
+
+    pub fn map(&self, maze_name: &str) -> Result<Map> {
+        let template = self
+            .get(maze_name)
+            .ok_or_else(|| anyhow!("Unknown maze: {}", maze_name))?;
+        Map::from_template(template)
+    }
+}
+
+/// Content hash identifying a maze template, clients cache mazes by it
+pub fn maze_hash(template: &str) -> String {
+    format!("{:x}", Sha256::digest(template.as_bytes()))
+}
+
+/// Checks the maze is rectangular, surrounded by walls and has an exit
+pub fn validate(template: &str) -> Result<Map> {
+    let map = Map::from_template(template)?;
+    let (width, height) = (map.width(), map.height());
+    ensure!(
+        width >= 3 && height >= 3,
+        "The maze has to be at least 3x3, got {}x{}",
+        width,
+        height
+    );
+
+    // the world only knows the objects it spawned itself, drawn ones would have no state
+    for y in 0..height {
+        for x in 0..width {
+            let type_ = map.get_object(&Point::new(x, y)).type_;
+            ensure!(
+                !matches!(
+                    type_,
+                    ObjectType::Player(_) | ObjectType::Shot(_) | ObjectType::Mob | ObjectType::Candy
+                ),
+                "The maze can't contain '{}' at {}:{}, players, shots, mobs and candies are placed by the game",
+                type_.to_char(),
+                x + 1,
+                y + 1
+            );
+        }
+    }
+
+    let border = (0..width)
+        .flat_map(|x| [Point::new(x, 0), Point::new(x, height - 1)])
+        .chain((0..height).flat_map(|y| [Point::new(0, y), Point::new(width - 1, y)]));
+    for point in border {
+        ensure!(
+            map.get_object(&point).type_ == ObjectType::Wall,
+            "The maze border is not walled at {}:{}",
+            point.x + 1,
+            point.y + 1
+        );
+    }
+
+    ensure!(map.count(ObjectType::Exit) > 0, "The maze has no exit 'X'");
+    ensure!(
+        map.count(ObjectType::Empty) > 0,
+        "The maze has no empty cells"
+    );
+    // every key opens one door, the configured keys are placed at random and can't be relied on
+    let (keys, doors) = (
+        map.count(ObjectType::Pickup(PickupKind::Key)),
+        map.count(ObjectType::Door),
+    );
+    ensure!(
+        keys >= doors,
+        "The maze has {} doors but only {} keys to open them",
+        doors,
+        keys
+    );
+    Ok(map)
+}
+
+/// Checks there is room for all mobs, candies and at least one player
+pub fn check_capacity(
+    map: &Map,
+    mob_cnt: usize,
+    candy_cnt: usize,
+    pickup_cnt: usize,
+) -> Result<()> {
+    let empty = map.count(ObjectType::Empty);
+    ensure!(
+        empty > mob_cnt + candy_cnt + pickup_cnt,
+        "The maze has only {} empty cells, not enough for {} mobs, {} candies, {} pickups and players",
+        empty,
+        mob_cnt,
+        candy_cnt,
+        pickup_cnt
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_mazes_are_valid() {
+        for (name, template) in MAZES.entries() {
+            assert!(validate(template).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn objects_placed_by_the_game_are_rejected() {
+        for glyph in ['*', '⏾', '^', 'v', '<', '>', '|', '-'] {
+            let template = format!("██████\n█ {glyph} X█\n██████");
+            assert!(validate(&template).is_err(), "{glyph}");
+        }
+        assert!(validate("██████\n█ ◊ X█\n██████").is_ok());
+    }
+
+    #[test]
+    fn doors_need_as_many_keys() {
+        assert!(validate("██████\n█ ▒ X█\n██████").is_err());
+        assert!(validate("██████\n█⚷▒ X█\n██████").is_ok());
+        assert!(validate("███████\n█⚷▒▒ X█\n███████").is_err());
+        assert!(validate("███████\n█⚷⚷▒ X█\n███████").is_ok());
+    }
+}
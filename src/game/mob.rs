@@ -0,0 +1,312 @@
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+use crate::game::map::{Direction, Map, ObjectType, Point};
+
+/// How far (in steps) chasers notice players
+const CHASE_RADIUS: usize = 15;
+/// How far (in steps) guards wander from the candy they guard
+const GUARD_RADIUS: usize = 3;
+const PATROL_WAYPOINTS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MobKind {
+    /// Steps in random directions
+    Walker,
+    /// Runs to the nearest player within reach, walks randomly otherwise
+    Chaser,
+    /// Walks a fixed route of waypoints over and over
+    Patroller,
+    /// Stays close to the nearest candy
+    Guard,
+}
+
+impl MobKind {
+    pub const ALL: [MobKind; 4] = [
+        MobKind::Walker,
+        MobKind::Chaser,
+        MobKind::Patroller,
+        MobKind::Guard,
+    ];
+
+    pub fn behavior(self) -> &'static dyn MobBehavior {
+        match self {
+            MobKind::Walker => &RandomWalker,
+            MobKind::Chaser => &Chaser,
+            MobKind::Patroller => &Patroller,
+            MobKind::Guard => &Guard,
+        }
+    }
+
+    pub fn default_ticks_per_move(self) -> u32 {
+        match self {
+            MobKind::Walker => 3,
+            MobKind::Chaser => 4,
+            MobKind::Patroller => 2,
+            MobKind::Guard => 3,
+        }
+    }
+}
+
+impl fmt::Display for MobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MobKind::Walker => "walker",
+            MobKind::Chaser => "chaser",
+            MobKind::Patroller => "patroller",
+            MobKind::Guard => "guard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How many mobs of a kind to spawn and how fast they are
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct MobConfig {
+    pub kind: MobKind,
+    pub count: usize,
+    /// The mob moves once every this many ticks
+    pub ticks_per_move: u32,
+}
+
+impl MobConfig {
+    pub fn new(kind: MobKind, count: usize) -> Self {
+        MobConfig {
+            kind,
+            count,
+            ticks_per_move: kind.default_ticks_per_move(),
+        }
+    }
+}
+
+/// Total number of mobs spawned by the configs
+pub fn mob_count(configs: &[MobConfig]) -> usize {
+    configs.iter().map(|config| config.count).sum()
+}
+
+#[derive(Clone, Debug)]
+pub struct Mob {
+    pub pos: Point,
+    pub kind: MobKind,
+    ticks_per_move: u32,
+    /// Ticks left until the next move
+    cooldown: u32,
+    /// Waypoints of patrollers
+    route: Vec<Point>,
+    route_idx: usize,
+}
+
+impl Mob {
+    /// Patrollers get their waypoints in empty cells of the `map`
+    pub fn new(pos: Point, config: &MobConfig, map: &Map, rng: &mut ChaCha8Rng) -> Self {
+        let route = match config.kind {
+            MobKind::Patroller => std::iter::once(pos)
+                .chain((1..PATROL_WAYPOINTS).filter_map(|_| map.random_empty_point(rng)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let ticks_per_move = config.ticks_per_move.max(1);
+        Mob {
+            pos,
+            kind: config.kind,
+            ticks_per_move,
+            cooldown: rng.gen_range(1..=ticks_per_move),
+            route,
+            route_idx: 0,
+        }
+    }
+
+    /// Counts the tick down, returns true when the mob gets to move this tick
+    pub fn ready(&mut self) -> bool {
+        self.cooldown -= 1;
+        if self.cooldown == 0 {
+            self.cooldown = self.ticks_per_move;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What mobs can see of the world when deciding where to go
+pub struct MobView<'a> {
+    /// Maze with all objects placed in it
+    pub map: &'a Map,
+    pub players: &'a BTreeMap<u64, Point>,
+    pub candies: &'a BTreeMap<u64, Point>,
+}
+
+pub trait MobBehavior {
+    /// Direction of the mob's next step, `None` to stay in place
+    fn next_step(&self, mob: &mut Mob, view: &MobView, rng: &mut ChaCha8Rng) -> Option<Direction>;
+}
+
+pub struct RandomWalker;
+
+impl MobBehavior for RandomWalker {
+    fn next_step(&self, _: &mut Mob, _: &MobView, rng: &mut ChaCha8Rng) -> Option<Direction> {
+        Some(rng.gen())
+    }
+}
+
+pub struct Chaser;
+
+impl MobBehavior for Chaser {
+    fn next_step(&self, mob: &mut Mob, view: &MobView, rng: &mut ChaCha8Rng) -> Option<Direction> {
+        let is_player = |pos: Point| view.players.values().any(|p| p.x == pos.x && p.y == pos.y);
+        first_step_towards(view.map, mob.pos, is_player, CHASE_RADIUS).or_else(|| Some(rng.gen()))
+    }
+}
+
+pub struct Patroller;
+
+impl MobBehavior for Patroller {
+    fn next_step(&self, mob: &mut Mob, view: &MobView, _: &mut ChaCha8Rng) -> Option<Direction> {
+        if mob.route.is_empty() {
+            return None;
+        }
+        let waypoint = mob.route[mob.route_idx];
+        if waypoint.x == mob.pos.x && waypoint.y == mob.pos.y {
+            mob.route_idx = (mob.route_idx + 1) % mob.route.len();
+        }
+        let waypoint = mob.route[mob.route_idx];
+        let is_waypoint = |pos: Point| pos.x == waypoint.x && pos.y == waypoint.y;
+        let step = first_step_towards(view.map, mob.pos, is_waypoint, usize::MAX);
+        if step.is_none() {
+            // walled off waypoint, head to the next one instead
+            mob.route_idx = (mob.route_idx + 1) % mob.route.len();
+        }
+        step
+    }
+}
+
+pub struct Guard;
+
+impl MobBehavior for Guard {
+    fn next_step(&self, mob: &mut Mob, view: &MobView, rng: &mut ChaCha8Rng) -> Option<Direction> {
+        let Some(candy) = view
+            .candies
+            .values()
+            .min_by_key(|candy| candy.distance(&mob.pos))
+        else {
+            return Some(rng.gen());
+        };
+        if candy.distance(&mob.pos) > GUARD_RADIUS {
+            let is_next_to_candy = |pos: Point| candy.distance(&pos) == 1;
+            return first_step_towards(view.map, mob.pos, is_next_to_candy, usize::MAX);
+        }
+        let dir: Direction = rng.gen();
+        let stays_close = candy.distance(&mob.pos.turn_and_step(dir)) <= GUARD_RADIUS;
+        stays_close.then_some(dir)
+    }
+}
+
+/// Whether mobs step into a cell holding the object, players there are killed unless shielded
+pub fn can_enter(type_: ObjectType) -> bool {
+    matches!(type_, ObjectType::Empty | ObjectType::Player(_))
+}
+
+/// Breadth-first search over cells mobs can enter, returns the first step of the shortest
+/// path to a cell matching `is_target` no further than `max_dist` steps
+fn first_step_towards(
+    map: &Map,
+    from: Point,
+    is_target: impl Fn(Point) -> bool,
+    max_dist: usize,
+) -> Option<Direction> {
+    const DIRECTIONS: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+    let start = Point::new(from.x, from.y);
+    // first step taken to reach each visited cell
+    let mut visited: HashMap<Point, Option<Direction>> = HashMap::from([(start, None)]);
+    let mut queue = VecDeque::from([(start, 0)]);
+
+    while let Some((pos, dist)) = queue.pop_front() {
+        if dist > 0 && is_target(pos) {
+            return visited[&pos];
+        }
+        if dist == max_dist {
+            continue;
+        }
+        for dir in DIRECTIONS {
+            let next = pos.turn_and_step(dir);
+            if can_enter(map.get_object(&next).type_) && !visited.contains_key(&next) {
+                let first_step = visited[&pos].or(Some(dir));
+                visited.insert(next, first_step);
+                queue.push_back((next, dist + 1));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn map(template: &str) -> Map {
+        Map::from_template(template).unwrap()
+    }
+
+    fn is_at(target: Point) -> impl Fn(Point) -> bool {
+        move |pos: Point| pos.x == target.x && pos.y == target.y
+    }
+
+    #[test]
+    fn paths_lead_around_candy() {
+        let map = map("███████\n█*⏾  >█\n█     █\n███████");
+        let step = first_step_towards(&map, Point::new(1, 1), is_at(Point::new(5, 1)), 10);
+        assert_eq!(step, Some(Direction::Down));
+    }
+
+    #[test]
+    fn candy_blocks_the_only_path() {
+        let map = map("█████\n█*⏾>█\n█████");
+        let step = first_step_towards(&map, Point::new(1, 1), is_at(Point::new(3, 1)), 10);
+        assert_eq!(step, None);
+    }
+
+    #[test]
+    fn doors_and_pickups_block_paths() {
+        for blocker in ['▒', '»', '⚷'] {
+            let map = map(&format!("█████\n█*{}>█\n█████", blocker));
+            let step = first_step_towards(&map, Point::new(1, 1), is_at(Point::new(3, 1)), 10);
+            assert_eq!(step, None, "path through {}", blocker);
+        }
+        let map = map("███████\n█*▒ ◊>█\n█     █\n███████");
+        let step = first_step_towards(&map, Point::new(1, 1), is_at(Point::new(5, 1)), 10);
+        assert_eq!(step, Some(Direction::Down));
+    }
+
+    #[test]
+    fn patrol_waypoints_avoid_doors_and_pickups() {
+        let map = map("█████\n█*▒⚷█\n█❄ ▒█\n█████");
+        let config = MobConfig::new(MobKind::Patroller, 1);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mob = Mob::new(Point::new(1, 1), &config, &map, &mut rng);
+        for waypoint in &mob.route[1..] {
+            assert_eq!((waypoint.x, waypoint.y), (2, 2));
+        }
+    }
+
+    #[test]
+    fn patrol_waypoints_avoid_candy() {
+        let map = map("█████\n█*⏾⏾█\n█⏾ ⏾█\n█████");
+        let config = MobConfig::new(MobKind::Patroller, 1);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mob = Mob::new(Point::new(1, 1), &config, &map, &mut rng);
+        assert!(mob.route.len() > 1);
+        for waypoint in &mob.route[1..] {
+            assert_eq!((waypoint.x, waypoint.y), (2, 2));
+        }
+    }
+}
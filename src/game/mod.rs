@@ -1,5 +1,18 @@
+pub mod accounts;
 pub mod api;
+pub mod event;
+pub mod leaderboard;
 pub mod map;
+pub mod maze_gen;
+pub mod maze_loader;
 pub mod mazes;
+pub mod mob;
+pub mod pickup;
+pub mod recording;
+pub mod rules;
+pub mod score;
+pub mod session;
+pub mod storage;
+pub mod visibility;
 pub mod world;
 pub mod world_controller;
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::game::map::Point;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickupKind {
+    /// Two actions per tick
+    Speed,
+    /// Mobs and shots can't kill the player
+    Shield,
+    /// Shots are fired forward and to both sides
+    MultiShot,
+    /// Mobs stop moving
+    Freeze,
+    /// Opens one door, kept until used
+    Key,
+}
+
+impl PickupKind {
+    pub const ALL: [PickupKind; 5] = [
+        PickupKind::Speed,
+        PickupKind::Shield,
+        PickupKind::MultiShot,
+        PickupKind::Freeze,
+        PickupKind::Key,
+    ];
+
+    pub fn default_duration_s(self) -> u64 {
+        match self {
+            PickupKind::Speed => 5,
+            PickupKind::Shield => 5,
+            PickupKind::MultiShot => 10,
+            PickupKind::Freeze => 3,
+            PickupKind::Key => 0,
+        }
+    }
+
+    /// Keys are held until used, other pickups give an effect that wears off
+    pub fn is_timed(self) -> bool {
+        self != PickupKind::Key
+    }
+}
+
+impl fmt::Display for PickupKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PickupKind::Speed => "speed",
+            PickupKind::Shield => "shield",
+            PickupKind::MultiShot => "multi_shot",
+            PickupKind::Freeze => "freeze",
+            PickupKind::Key => "key",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How many pickups of a kind to spawn, how long their effect lasts and whether they come back
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PickupConfig {
+    pub kind: PickupKind,
+    pub count: usize,
+    /// Seconds the effect lasts, the kind's default if missing
+    #[serde(default)]
+    pub duration_s: Option<u64>,
+    /// A collected pickup spawns again at a random place this many seconds later, never if missing
+    #[serde(default)]
+    pub respawn_s: Option<u64>,
+}
+
+impl PickupConfig {
+    pub fn new(kind: PickupKind, count: usize) -> Self {
+        PickupConfig {
+            kind,
+            count,
+            duration_s: None,
+            respawn_s: None,
+        }
+    }
+
+    pub fn duration_ticks(&self, tick_ms: u64) -> u64 {
+        let duration_s = self.duration_s.unwrap_or(self.kind.default_duration_s());
+        seconds_to_ticks(duration_s, tick_ms)
+    }
+
+    pub fn respawn_ticks(&self, tick_ms: u64) -> Option<u64> {
+        self.respawn_s
+            .map(|respawn_s| seconds_to_ticks(respawn_s, tick_ms))
+    }
+}
+
+/// Total number of pickups spawned by the configs
+pub fn pickup_count(configs: &[PickupConfig]) -> usize {
+    configs.iter().map(|config| config.count).sum()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pickup {
+    pub pos: Point,
+    pub kind: PickupKind,
+}
+
+fn seconds_to_ticks(seconds: u64, tick_ms: u64) -> u64 {
+    seconds.saturating_mul(1000).div_ceil(tick_ms.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds_are_converted_with_the_tick_length() {
+        let mut config = PickupConfig::new(PickupKind::MultiShot, 1);
+        assert_eq!(config.duration_ticks(100), 100);
+        assert_eq!(config.duration_ticks(250), 40);
+        assert_eq!(config.respawn_ticks(100), None);
+
+        config.duration_s = Some(1);
+        config.respawn_s = Some(1);
+        assert_eq!(config.duration_ticks(300), 4);
+        assert_eq!(config.respawn_ticks(1000), Some(1));
+    }
+}
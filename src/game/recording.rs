@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::game::api::PlayerAction;
+use crate::game::event::GameEvent;
+use crate::game::map::Map;
+use crate::game::mob::MobConfig;
+use crate::game::pickup::PickupConfig;
+use crate::game::rules::{GameMode, LivesConfig};
+use crate::game::visibility::Visibility;
+use crate::game::world::World;
+
+pub const RECORDING_EXTENSION: &str = "replay";
+
+/// Everything needed to recreate the world the recording starts from
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordingHeader {
+    pub maze: String,
+    pub seed: u64,
+    pub mobs: Vec<MobConfig>,
+    pub candy_cnt: usize,
+    #[serde(default)]
+    pub mode: GameMode,
+    #[serde(default)]
+    pub lives: LivesConfig,
+    #[serde(default)]
+    pub pickups: Vec<PickupConfig>,
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// Ticks the game lasts at most, if it's limited
+    pub time_limit: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum RecordedInput {
+    Join {
+        name: String,
+    },
+    Leave {
+        player_id: u64,
+    },
+    Action {
+        player_id: u64,
+        action: PlayerAction,
+    },
+}
+
+/// Inputs in the order they were applied during one tick and the events the tick produced
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TickRecord {
+    pub inputs: Vec<RecordedInput>,
+    pub events: Vec<GameEvent>,
+}
+
+/// A whole game, replayed by feeding the recorded inputs to a world with the same seed. Files
+/// hold the recording followed by the ticks appended by a `RecordingWriter`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Recording {
+    pub tick_ms: u64,
+    pub header: RecordingHeader,
+    pub ticks: Vec<TickRecord>,
+}
+
+impl Recording {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("Couldn't create recording {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        rmp_serde::encode::write(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Couldn't open recording {:?}", path))?;
+        let mut deserializer = rmp_serde::Deserializer::new(BufReader::new(file));
+        let mut recording = Recording::deserialize(&mut deserializer)
+            .with_context(|| format!("Invalid recording {:?}", path))?;
+        // appended ticks run until the end of the file, the last one may be cut off by a crash
+        while let Ok(tick) = TickRecord::deserialize(&mut deserializer) {
+            recording.ticks.push(tick);
+        }
+        Ok(recording)
+    }
+
+    /// Rebuilds the world as it was after the first `tick_cnt` ticks
+    pub fn world_at(&self, tick_cnt: usize) -> Result<World> {
+        let map = Map::from_template(&self.header.maze)?;
+        let mut world = World::with_seed(
+            map,
+            &self.header.mobs,
+            self.header.candy_cnt,
+            &self.header.pickups,
+            self.header.mode,
+            self.header.lives,
+            self.header.seed,
+        )
+        .with_visibility(self.header.visibility)
+        .with_time_limit(self.header.time_limit)
+        .with_tick_ms(self.tick_ms);
+        for tick in self.ticks.iter().take(tick_cnt) {
+            world.replay_tick(tick);
+        }
+        Ok(world)
+    }
+}
+
+/// Appends the ticks of a game in progress to its recording file, so that the recording doesn't
+/// have to be kept in memory
+pub struct RecordingWriter {
+    writer: BufWriter<File>,
+}
+
+impl RecordingWriter {
+    /// New recording file starting with `recording`
+    pub fn create(path: &Path, recording: &Recording) -> Result<Self> {
+        recording.save(path)?;
+        RecordingWriter::append(path)
+    }
+
+    /// Continues the recording file
+    pub fn append(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Couldn't open recording {:?}", path))?;
+        Ok(RecordingWriter {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, ticks: &[TickRecord]) -> Result<()> {
+        for tick in ticks {
+            rmp_serde::encode::write(&mut self.writer, tick)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::api::PlayerAction;
+    use crate::game::map::Direction;
+    use std::fs;
+
+    fn played_world() -> World {
+        let map = Map::from_template("███████\n█     █\n█    X█\n███████").unwrap();
+        let mut world = World::with_seed(
+            map,
+            &[],
+            0,
+            &[],
+            GameMode::Classic,
+            LivesConfig::default(),
+            7,
+        )
+        .with_recording();
+        let player_id = world.spawn_player("alice").unwrap();
+        for _ in 0..3 {
+            world.queue_action(player_id, PlayerAction::Move(Direction::Right));
+            world.move_world();
+        }
+        world
+    }
+
+    #[test]
+    fn loads_appended_ticks_and_drops_a_cut_off_one() {
+        let path = std::env::temp_dir().join(format!("recording-{}.replay", std::process::id()));
+        let mut world = played_world();
+        let mut ticks = world.take_recorded_ticks();
+        let later = ticks.split_off(1);
+        let recording = Recording {
+            tick_ms: 100,
+            header: world.recording_header().clone(),
+            ticks,
+        };
+
+        let mut writer = RecordingWriter::create(&path, &recording).unwrap();
+        writer.write(&later).unwrap();
+        drop(writer);
+        let complete_len = fs::metadata(&path).unwrap().len();
+        RecordingWriter::append(&path)
+            .unwrap()
+            .write(&later[..1])
+            .unwrap();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(complete_len + 1).unwrap();
+
+        let loaded = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.ticks.len(), 3);
+        let replayed = loaded.world_at(loaded.ticks.len()).unwrap();
+        assert_eq!(replayed.get_map().format(), world.get_map().format());
+    }
+
+    #[test]
+    fn only_recording_worlds_keep_ticks() {
+        let mut world = played_world();
+        assert_eq!(world.take_recorded_ticks().len(), 3);
+        assert!(world.take_recorded_ticks().is_empty());
+
+        let mut replayed = Recording {
+            tick_ms: 100,
+            header: world.recording_header().clone(),
+            ticks: Vec::new(),
+        }
+        .world_at(0)
+        .unwrap();
+        replayed.move_world();
+        assert!(replayed.take_recorded_ticks().is_empty());
+    }
+}
@@ -0,0 +1,419 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::game::api::PlayerStats;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// First player to reach the exit once all candies are collected wins
+    #[default]
+    Classic,
+    /// Everybody wins once all survivors reached the exit, shots don't hurt players
+    Cooperative,
+    /// Two teams, the whole team wins once any of its members reaches the exit
+    TeamRace,
+    /// Last player alive wins, or whoever of the survivors killed most when time runs out
+    Deathmatch,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [
+        GameMode::Classic,
+        GameMode::Cooperative,
+        GameMode::TeamRace,
+        GameMode::Deathmatch,
+    ];
+
+    pub fn rules(self) -> &'static dyn GameRules {
+        match self {
+            GameMode::Classic => &Classic,
+            GameMode::Cooperative => &Cooperative,
+            GameMode::TeamRace => &TeamRace,
+            GameMode::Deathmatch => &Deathmatch,
+        }
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GameMode::Classic => "classic",
+            GameMode::Cooperative => "cooperative",
+            GameMode::TeamRace => "team_race",
+            GameMode::Deathmatch => "deathmatch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How many lives players have and how long they wait to come back after losing one
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LivesConfig {
+    pub lives: u32,
+    pub respawn_ticks: u64,
+}
+
+impl LivesConfig {
+    /// `respawn_ms` is rounded up to whole ticks
+    pub fn new(lives: u32, respawn_ms: u64, tick_ms: u64) -> Self {
+        LivesConfig {
+            lives: lives.max(1),
+            respawn_ticks: respawn_ms.div_ceil(tick_ms.max(1)),
+        }
+    }
+}
+
+impl Default for LivesConfig {
+    /// A single life, as in games recorded before players could respawn
+    fn default() -> Self {
+        LivesConfig {
+            lives: 1,
+            respawn_ticks: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl fmt::Display for Team {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Team::Red => "red",
+            Team::Blue => "blue",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What the rules get to see of the world
+pub struct RulesView<'a> {
+    /// Players in the game in the order they joined
+    pub players: &'a [u64],
+    /// Players who lost all their lives, those waiting to respawn are still alive
+    pub dead: &'a [u64],
+    /// Players who reached the exit, in the order they did
+    pub exited: &'a [u64],
+    /// Teams of everybody who ever joined, including players who left
+    pub teams: &'a HashMap<u64, Team>,
+    pub stats: &'a HashMap<u64, PlayerStats>,
+    /// Ticks the world moved so far
+    pub tick: u64,
+    /// Ticks the game lasts at most, if it's limited
+    pub time_limit: Option<u64>,
+}
+
+impl RulesView<'_> {
+    fn alive(&self) -> impl Iterator<Item = u64> + '_ {
+        self.players
+            .iter()
+            .copied()
+            .filter(|player| !self.dead.contains(player))
+    }
+
+    fn all_dead(&self) -> bool {
+        !self.players.is_empty() && self.alive().next().is_none()
+    }
+}
+
+pub trait GameRules {
+    /// Team of a player joining the game, `None` if the mode has no teams
+    fn assign_team(&self, _view: &RulesView) -> Option<Team> {
+        None
+    }
+
+    /// Whether players leave the maze through the exit once all candies are collected
+    fn exit_open(&self) -> bool {
+        true
+    }
+
+    /// Whether a shot of `shooter` kills `victim`, the shooter is unknown for stray shots
+    fn can_hurt(&self, _view: &RulesView, _shooter: Option<u64>, _victim: u64) -> bool {
+        true
+    }
+
+    /// Winners once the game is over, `None` while it goes on
+    fn outcome(&self, view: &RulesView) -> Option<Vec<u64>>;
+}
+
+pub struct Classic;
+
+impl GameRules for Classic {
+    fn outcome(&self, view: &RulesView) -> Option<Vec<u64>> {
+        if let Some(first) = view.exited.first() {
+            return Some(vec![*first]);
+        }
+        view.all_dead().then(Vec::new)
+    }
+}
+
+pub struct Cooperative;
+
+impl GameRules for Cooperative {
+    fn can_hurt(&self, _: &RulesView, _: Option<u64>, _: u64) -> bool {
+        false
+    }
+
+    fn outcome(&self, view: &RulesView) -> Option<Vec<u64>> {
+        if view.players.is_empty() {
+            return None;
+        }
+        view.alive()
+            .all(|player| view.exited.contains(&player))
+            .then(|| view.exited.to_vec())
+    }
+}
+
+pub struct TeamRace;
+
+impl GameRules for TeamRace {
+    /// Joins the smaller team, red on a tie
+    fn assign_team(&self, view: &RulesView) -> Option<Team> {
+        let members = |team| {
+            view.players
+                .iter()
+                .filter(|player| view.teams.get(player) == Some(&team))
+                .count()
+        };
+        if members(Team::Blue) < members(Team::Red) {
+            Some(Team::Blue)
+        } else {
+            Some(Team::Red)
+        }
+    }
+
+    fn can_hurt(&self, view: &RulesView, shooter: Option<u64>, victim: u64) -> bool {
+        match shooter {
+            Some(shooter) => view.teams.get(&shooter) != view.teams.get(&victim),
+            None => true,
+        }
+    }
+
+    fn outcome(&self, view: &RulesView) -> Option<Vec<u64>> {
+        if let Some(first) = view.exited.first() {
+            let team = view.teams.get(first);
+            return Some(
+                view.players
+                    .iter()
+                    .copied()
+                    .filter(|player| view.teams.get(player) == team)
+                    .collect(),
+            );
+        }
+        view.all_dead().then(Vec::new)
+    }
+}
+
+pub struct Deathmatch;
+
+impl GameRules for Deathmatch {
+    fn exit_open(&self) -> bool {
+        false
+    }
+
+    fn outcome(&self, view: &RulesView) -> Option<Vec<u64>> {
+        if view.all_dead() {
+            return Some(Vec::new());
+        }
+        let alive: Vec<u64> = view.alive().collect();
+        if view.players.len() > 1 && alive.len() == 1 {
+            return Some(alive);
+        }
+        if view.time_limit.is_none_or(|limit| view.tick < limit) {
+            return None;
+        }
+        let kills = |player: &u64| view.stats.get(player).map_or(0, |stats| stats.player_kills);
+        let most = alive.iter().map(kills).max().unwrap_or(0);
+        Some(
+            alive
+                .into_iter()
+                .filter(|player| kills(player) == most)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::api::PlayerAction;
+    use crate::game::map::{Direction, Map, ObjectType, Point};
+    use crate::game::world::World;
+
+    /// A corridor along the second row, with the exit at its right end
+    fn corridor(len: usize, mode: GameMode) -> World {
+        let wall = "█".repeat(len + 3);
+        let template = format!("{wall}\n█{}X█\n{wall}", " ".repeat(len));
+        let map = Map::from_template(&template).unwrap();
+        World::with_seed(map, &[], 0, &[], mode, LivesConfig::default(), 1)
+    }
+
+    fn join(world: &mut World, count: usize) {
+        for i in 0..count {
+            world.spawn_player(&format!("player{i}")).unwrap();
+        }
+    }
+
+    /// Players in the corridor, the one closest to the exit last
+    fn left_to_right(world: &World) -> Vec<u64> {
+        let map = world.get_map();
+        (0..map.width())
+            .map(|x| map.get_object(&Point::new(x, 1)))
+            .filter(|object| matches!(object.type_, ObjectType::Player(_)))
+            .map(|object| object.id)
+            .collect()
+    }
+
+    /// Everybody walks towards the exit until the game is over
+    fn run_to_exit(world: &mut World, players: &[u64]) {
+        for _ in 0..10 {
+            if world.is_finished() {
+                return;
+            }
+            for player in players {
+                world.queue_action(*player, PlayerAction::Move(Direction::Right));
+            }
+            world.move_world();
+        }
+        panic!("The game didn't finish");
+    }
+
+    /// The left player turns to the right one and fires at point blank range
+    fn shoot_neighbour(world: &mut World, shooter: u64) {
+        world.queue_action(shooter, PlayerAction::Move(Direction::Right));
+        world.move_world();
+        world.queue_action(shooter, PlayerAction::Shoot);
+        world.move_world();
+    }
+
+    fn sorted(mut players: Vec<u64>) -> Vec<u64> {
+        players.sort();
+        players
+    }
+
+    #[test]
+    fn classic_is_won_by_the_first_to_exit() {
+        let mut world = corridor(3, GameMode::Classic);
+        join(&mut world, 2);
+        let players = left_to_right(&world);
+
+        run_to_exit(&mut world, &players);
+
+        assert_eq!(world.winners(), &players[1..]);
+    }
+
+    #[test]
+    fn classic_players_can_shoot_each_other() {
+        let mut world = corridor(2, GameMode::Classic);
+        join(&mut world, 2);
+        let players = left_to_right(&world);
+
+        shoot_neighbour(&mut world, players[0]);
+
+        assert!(!world.can_play(players[1]));
+        assert!(world.can_play(players[0]));
+    }
+
+    #[test]
+    fn cooperative_is_won_by_everybody_once_all_exit() {
+        let mut world = corridor(2, GameMode::Cooperative);
+        join(&mut world, 2);
+        let players = left_to_right(&world);
+
+        shoot_neighbour(&mut world, players[0]);
+        assert!(world.can_play(players[1]));
+
+        // the first one out waits for the other
+        world.queue_action(players[1], PlayerAction::Move(Direction::Right));
+        world.move_world();
+        assert!(!world.is_finished());
+
+        run_to_exit(&mut world, &players[..1]);
+        assert_eq!(sorted(world.winners().to_vec()), sorted(players));
+    }
+
+    #[test]
+    fn team_race_is_won_by_the_team_of_the_first_to_exit() {
+        let mut world = corridor(3, GameMode::TeamRace);
+        join(&mut world, 3);
+        let players = left_to_right(&world);
+        let scores = world.scores();
+        let team = |player: u64| {
+            let score = scores.iter().find(|score| score.player_id == player);
+            score.and_then(|score| score.team)
+        };
+        let red = scores
+            .iter()
+            .filter(|score| score.team == Some(Team::Red))
+            .count();
+        assert_eq!(red, 2);
+
+        run_to_exit(&mut world, &players);
+
+        let first = players[2];
+        let expected: Vec<u64> = players
+            .iter()
+            .copied()
+            .filter(|player| team(*player) == team(first))
+            .collect();
+        assert_eq!(sorted(world.winners().to_vec()), sorted(expected));
+    }
+
+    #[test]
+    fn team_race_players_only_hurt_the_other_team() {
+        let mut world = corridor(2, GameMode::TeamRace);
+        join(&mut world, 2);
+        let players = left_to_right(&world);
+        let rules = GameMode::TeamRace.rules();
+        let teams = HashMap::from([(1, Team::Red), (2, Team::Blue), (3, Team::Red)]);
+        let view = RulesView {
+            players: &[1, 2, 3],
+            dead: &[],
+            exited: &[],
+            teams: &teams,
+            stats: &HashMap::new(),
+            tick: 0,
+            time_limit: None,
+        };
+        assert!(rules.can_hurt(&view, Some(1), 2));
+        assert!(!rules.can_hurt(&view, Some(1), 3));
+        assert!(rules.can_hurt(&view, None, 3));
+
+        // two players always end up in different teams
+        shoot_neighbour(&mut world, players[0]);
+        assert!(!world.can_play(players[1]));
+    }
+
+    #[test]
+    fn deathmatch_is_won_by_the_last_one_alive() {
+        let mut world = corridor(2, GameMode::Deathmatch);
+        join(&mut world, 2);
+        let players = left_to_right(&world);
+
+        shoot_neighbour(&mut world, players[0]);
+
+        assert!(world.is_finished());
+        assert_eq!(world.winners(), &players[..1]);
+    }
+
+    #[test]
+    fn deathmatch_ends_at_the_time_limit_with_the_survivors_tied() {
+        let mut world = corridor(2, GameMode::Deathmatch).with_time_limit(Some(3));
+        join(&mut world, 2);
+        let players = left_to_right(&world);
+
+        world.move_world();
+        world.move_world();
+        assert!(!world.is_finished());
+        world.move_world();
+
+        assert!(world.is_finished());
+        assert_eq!(sorted(world.winners().to_vec()), sorted(players));
+    }
+}
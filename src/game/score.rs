@@ -0,0 +1,23 @@
+use crate::game::api::PlayerStats;
+
+pub const CANDY_POINTS: i64 = 10;
+pub const MOB_KILL_POINTS: i64 = 5;
+pub const PLAYER_KILL_POINTS: i64 = 20;
+pub const DEATH_PENALTY: i64 = 30;
+pub const EXIT_POINTS: i64 = 50;
+/// Bonus for reaching the exit, one point is lost every `TIME_BONUS_TICKS` ticks spent in the game
+pub const TIME_BONUS: i64 = 100;
+pub const TIME_BONUS_TICKS: u64 = 10;
+
+/// Points for what the player achieved, `exit_ticks` is how long it took them to reach the exit
+pub fn points(stats: &PlayerStats, exit_ticks: Option<u64>) -> i64 {
+    let mut points = stats.candies as i64 * CANDY_POINTS
+        + stats.mob_kills as i64 * MOB_KILL_POINTS
+        + stats.player_kills as i64 * PLAYER_KILL_POINTS
+        - stats.deaths as i64 * DEATH_PENALTY;
+    if let Some(ticks) = exit_ticks {
+        let time_bonus = TIME_BONUS - (ticks / TIME_BONUS_TICKS).min(TIME_BONUS as u64) as i64;
+        points += EXIT_POINTS + time_bonus;
+    }
+    points
+}
@@ -0,0 +1,38 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Random bytes in a session token
+const TOKEN_BYTES: usize = 32;
+
+/// Unguessable token authenticating a player's requests, handed out when joining a game
+pub fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The server only keeps token hashes, so leaked state files don't leak sessions
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let token = new_token();
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert!(token.chars().all(|ch| ch.is_ascii_hexdigit()));
+        assert_ne!(token, new_token());
+    }
+
+    #[test]
+    fn hashes_are_stable_and_differ_by_token() {
+        let token = new_token();
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+        assert_ne!(hash_token(&token), hash_token(&new_token()));
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::game::api::GamePhase;
+use crate::game::maze_gen::MazeSpec;
+use crate::game::recording::{Recording, RecordingWriter, RECORDING_EXTENSION};
+
+const GAME_EXTENSION: &str = "json";
+/// Subdirectory of archived games, which aren't loaded on startup
+const ARCHIVE_DIR: &str = "archive";
+
+/// How a finished game ended
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameResult {
+    /// Empty if nobody won, several players win together in team modes
+    #[serde(default)]
+    pub winners: Vec<String>,
+    /// World ticks it took to finish the game
+    pub ticks: u64,
+}
+
+/// Server side game metadata, the world itself is stored as its recording and rebuilt by
+/// replaying it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedGame {
+    pub id: u64,
+    pub name: String,
+    pub maze_name: String,
+    pub maze_hash: String,
+    pub maze_spec: Option<MazeSpec>,
+    pub players: HashMap<u64, String>,
+    /// Player ids by their session token hash
+    #[serde(default)]
+    pub sessions: HashMap<String, u64>,
+    /// Accounts of the logged in players
+    #[serde(default)]
+    pub accounts: HashMap<u64, String>,
+    /// Players whose stats were added to their accounts
+    pub credited: HashSet<u64>,
+    pub tick_ms: u64,
+    #[serde(default)]
+    pub countdown_ms: u64,
+    pub phase: GamePhase,
+    pub result: Option<GameResult>,
+}
+
+/// Writes the file through a temporary one, so that a crash never leaves it half written
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes).with_context(|| format!("Couldn't write {:?}", tmp))?;
+    fs::rename(tmp, path).with_context(|| format!("Couldn't replace {:?}", path))?;
+    Ok(())
+}
+
+/// Directory with a `<id>.json` metadata file and a `<id>.replay` recording per game
+pub struct GameStore {
+    dir: PathBuf,
+    /// Ids of the games moved to the archive. Saving and archiving hold this lock, so that a save
+    /// racing the archiving never writes a game back after it was moved.
+    archived: Mutex<HashSet<u64>>,
+}
+
+impl GameStore {
+    pub fn new(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Couldn't create state directory {:?}", dir))?;
+        Ok(GameStore {
+            dir: dir.to_path_buf(),
+            archived: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn path(&self, game_id: u64, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", game_id, extension))
+    }
+
+    /// Stores the game's recording so far, the returned writer appends the following ticks
+    pub fn start_recording(&self, game_id: u64, recording: &Recording) -> Result<RecordingWriter> {
+        // written to a temporary file first so that a crash never leaves a half written recording
+        let recording_tmp = self.path(game_id, "replay.tmp");
+        recording.save(&recording_tmp)?;
+        let path = self.path(game_id, RECORDING_EXTENSION);
+        fs::rename(recording_tmp, &path)?;
+        RecordingWriter::append(&path)
+    }
+
+    /// Saves the game's metadata, its recording is kept up to date by `start_recording`. Archived
+    /// games aren't saved anymore.
+    pub fn save(&self, game: &SavedGame) -> Result<()> {
+        let archived = self.archived.lock().unwrap_or_else(|err| err.into_inner());
+        if archived.contains(&game.id) {
+            return Ok(());
+        }
+        self.write(game)
+    }
+
+    fn write(&self, game: &SavedGame) -> Result<()> {
+        write_atomic(
+            &self.path(game.id, GAME_EXTENSION),
+            &serde_json::to_vec_pretty(game)?,
+        )
+    }
+
+    /// Saves the game one last time and moves it to the archive
+    pub fn archive(&self, game: &SavedGame) -> Result<()> {
+        let mut archived = self.archived.lock().unwrap_or_else(|err| err.into_inner());
+        archived.insert(game.id);
+        self.write(game)?;
+        let archive_dir = self.dir.join(ARCHIVE_DIR);
+        fs::create_dir_all(&archive_dir)
+            .with_context(|| format!("Couldn't create archive directory {:?}", archive_dir))?;
+        for extension in [RECORDING_EXTENSION, GAME_EXTENSION] {
+            let file_name = format!("{}.{}", game.id, extension);
+            fs::rename(self.dir.join(&file_name), archive_dir.join(file_name))?;
+        }
+        Ok(())
+    }
+
+    fn load(&self, path: &Path) -> Result<(SavedGame, Recording)> {
+        let game: SavedGame = serde_json::from_slice(&fs::read(path)?)
+            .with_context(|| format!("Invalid saved game {:?}", path))?;
+        let recording = Recording::load(&self.path(game.id, RECORDING_EXTENSION))?;
+        Ok((game, recording))
+    }
+
+    /// Loads every stored game that isn't archived, ordered by id. Games that can't be read are
+    /// left out, so that one broken file doesn't keep the server from starting.
+    pub fn load_all(&self) -> Result<Vec<(SavedGame, Recording)>> {
+        let mut games = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_game = path.extension() == Some(GAME_EXTENSION.as_ref())
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| stem.parse::<u64>().is_ok());
+            if !is_game {
+                continue;
+            }
+            match self.load(&path) {
+                Ok(game) => games.push(game),
+                Err(err) => error!("Skipped saved game {:?}: {:?}", path, err),
+            }
+        }
+        games.sort_by_key(|(game, _)| game.id);
+        Ok(games)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::map::Map;
+    use crate::game::rules::{GameMode, LivesConfig};
+    use crate::game::world::World;
+
+    fn store(name: &str) -> GameStore {
+        let dir = std::env::temp_dir().join(format!("store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        GameStore::new(&dir).unwrap()
+    }
+
+    fn saved_game(id: u64) -> SavedGame {
+        SavedGame {
+            id,
+            name: "test".to_string(),
+            maze_name: "test".to_string(),
+            maze_hash: String::new(),
+            maze_spec: None,
+            players: HashMap::new(),
+            sessions: HashMap::new(),
+            accounts: HashMap::new(),
+            credited: HashSet::new(),
+            tick_ms: 100,
+            countdown_ms: 0,
+            phase: GamePhase::Running,
+            result: None,
+        }
+    }
+
+    fn start(store: &GameStore, id: u64) {
+        let map = Map::from_template("█████\n█  X█\n█████").unwrap();
+        let world = World::with_seed(
+            map,
+            &[],
+            0,
+            &[],
+            GameMode::Classic,
+            LivesConfig::default(),
+            1,
+        );
+        let recording = Recording {
+            tick_ms: 100,
+            header: world.recording_header().clone(),
+            ticks: Vec::new(),
+        };
+        store.start_recording(id, &recording).unwrap();
+        store.save(&saved_game(id)).unwrap();
+    }
+
+    #[test]
+    fn archived_games_arent_saved_again() {
+        let store = store("archive");
+        start(&store, 1);
+        store.archive(&saved_game(1)).unwrap();
+        store.save(&saved_game(1)).unwrap();
+
+        assert!(!store.path(1, GAME_EXTENSION).exists());
+        assert!(store.load_all().unwrap().is_empty());
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn games_that_cant_be_read_are_skipped() {
+        let store = store("broken");
+        start(&store, 1);
+        start(&store, 2);
+        fs::remove_file(store.path(1, RECORDING_EXTENSION)).unwrap();
+
+        let loaded = store.load_all().unwrap();
+        let ids: Vec<u64> = loaded.iter().map(|(game, _)| game.id).collect();
+        assert_eq!(ids, [2]);
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::game::map::{Map, ObjectType, Point};
+
+/// Fog of war, players only see the part of the maze around them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Visibility {
+    /// How many cells far players see, unlimited if missing
+    #[serde(default)]
+    pub radius: Option<usize>,
+    /// Walls block the view
+    #[serde(default)]
+    pub line_of_sight: bool,
+}
+
+impl Visibility {
+    /// Without a radius or line of sight players would see everything anyway
+    pub fn is_limited(&self) -> bool {
+        self.radius.is_some() || self.line_of_sight
+    }
+
+    fn can_see(&self, map: &Map, from: &Point, to: &Point) -> bool {
+        if let Some(radius) = self.radius {
+            let (dx, dy) = (from.x.abs_diff(to.x), from.y.abs_diff(to.y));
+            if dx * dx + dy * dy > radius * radius {
+                return false;
+            }
+        }
+        // lines drawn the other way pass ties on the other side, trying both lets players look
+        // diagonally past corners and see each other either both or neither
+        !self.line_of_sight || line_clear(map, from, to) || line_clear(map, to, from)
+    }
+}
+
+/// Whether no wall stands on the straight line between the cells, the cells themselves may be
+/// walls so that players see the walls around them
+fn line_clear(map: &Map, from: &Point, to: &Point) -> bool {
+    let (mut x, mut y) = (from.x as isize, from.y as isize);
+    let (to_x, to_y) = (to.x as isize, to.y as isize);
+    let (dx, dy) = ((to_x - x).abs(), -(to_y - y).abs());
+    let (step_x, step_y) = ((to_x - x).signum(), (to_y - y).signum());
+    let mut err = dx + dy;
+    loop {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += step_x;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += step_y;
+        }
+        if (x, y) == (to_x, to_y) {
+            return true;
+        }
+        if map.get_object(&Point::new(x as usize, y as usize)).type_ == ObjectType::Wall {
+            return false;
+        }
+    }
+}
+
+/// What a player sees of the maze at the moment
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Sight {
+    /// The whole maze, in games without fog of war and for players out of the game for good
+    Everything,
+    /// Cells the visibility lets them see from where they stand
+    Around { from: Point, visibility: Visibility },
+    /// Nothing at all while waiting to respawn
+    Nothing,
+}
+
+impl Sight {
+    pub fn can_see(&self, map: &Map, pos: &Point) -> bool {
+        match self {
+            Sight::Everything => true,
+            Sight::Around { from, visibility } => visibility.can_see(map, from, pos),
+            Sight::Nothing => false,
+        }
+    }
+
+    /// All cells of the maze in sight
+    pub fn visible_cells(&self, map: &Map) -> HashSet<Point> {
+        let (mut min, mut max) = ((0, 0), (map.width(), map.height()));
+        match self {
+            Sight::Nothing => return HashSet::new(),
+            Sight::Around {
+                from,
+                visibility:
+                    Visibility {
+                        radius: Some(radius),
+                        ..
+                    },
+            } => {
+                min = (
+                    from.x.saturating_sub(*radius),
+                    from.y.saturating_sub(*radius),
+                );
+                max = (
+                    (from.x + radius + 1).min(map.width()),
+                    (from.y + radius + 1).min(map.height()),
+                );
+            }
+            _ => {}
+        }
+        (min.1..max.1)
+            .flat_map(|y| (min.0..max.0).map(move |x| Point::new(x, y)))
+            .filter(|pos| self.can_see(map, pos))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(template: &str) -> Map {
+        Map::from_template(template).unwrap()
+    }
+
+    fn around(from: Point, radius: Option<usize>, line_of_sight: bool) -> Sight {
+        Sight::Around {
+            from,
+            visibility: Visibility {
+                radius,
+                line_of_sight,
+            },
+        }
+    }
+
+    #[test]
+    fn walls_block_lines_but_end_them() {
+        let map = map("███████\n█     █\n█  █  █\n█     █\n███████");
+        let (left, right) = (Point::new(1, 2), Point::new(5, 2));
+        assert!(!line_clear(&map, &left, &right));
+        assert!(!line_clear(&map, &right, &left));
+        assert!(line_clear(&map, &Point::new(1, 1), &Point::new(5, 1)));
+        // the wall itself is in sight
+        assert!(line_clear(&map, &left, &Point::new(3, 2)));
+        assert!(line_clear(&map, &left, &Point::new(0, 2)));
+    }
+
+    #[test]
+    fn players_see_each_other_past_corners_both_ways() {
+        let map = map("█████\n█ █ █\n█   █\n█████");
+        let (a, b) = (Point::new(1, 1), Point::new(2, 2));
+        let sight_a = around(a, None, true);
+        let sight_b = around(b, None, true);
+        assert_eq!(sight_a.can_see(&map, &b), sight_b.can_see(&map, &a));
+        assert!(sight_a.can_see(&map, &Point::new(3, 2)));
+        assert!(!sight_a.can_see(&map, &Point::new(3, 1)));
+    }
+
+    #[test]
+    fn radius_limits_the_visible_cells() {
+        let map = map("███████\n█     █\n█     █\n█     █\n███████");
+        let center = Point::new(3, 2);
+        let cells = around(center, Some(1), false).visible_cells(&map);
+        let mut expected = HashSet::from([center]);
+        expected.extend([(2, 2), (4, 2), (3, 1), (3, 3)].map(|(x, y)| Point::new(x, y)));
+        assert_eq!(cells, expected);
+
+        assert_eq!(Sight::Everything.visible_cells(&map).len(), 35);
+        assert!(Sight::Nothing.visible_cells(&map).is_empty());
+    }
+}
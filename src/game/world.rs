@@ -1,7 +1,19 @@
 use chrono::Local;
-use std::collections::{HashMap, HashSet};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 
+use crate::game::api::{ObjectsUpdate, PlayerAction, PlayerScore, PlayerStats};
+use crate::game::event::{GameEvent, Killer, LoggedEvent, PlayerRef};
 use crate::game::map::{Direction, Map, ObjectType, Point};
+use crate::game::mob::{can_enter, Mob, MobConfig, MobKind, MobView};
+use crate::game::pickup::{Pickup, PickupConfig, PickupKind};
+use crate::game::recording::{RecordedInput, RecordingHeader, TickRecord};
+use crate::game::rules::{GameMode, GameRules, LivesConfig, RulesView, Team};
+use crate::game::score;
+use crate::game::visibility::{Sight, Visibility};
+use crate::game::world_controller::DEFAULT_TICK_MS;
 
 use rand::{
     distributions::{Distribution, Standard},
@@ -19,90 +31,713 @@ impl Distribution<Direction> for Standard {
     }
 }
 
-#[derive(serde::Serialize)]
+/// How many past ticks are kept to compute deltas against
+const HISTORY_LEN: usize = 100;
+/// How many of the latest events are sent along with a full snapshot
+const SNAPSHOT_EVENT_CNT: usize = 50;
+/// Actions over this limit are dropped until the player's queue drains
+const MAX_QUEUED_ACTIONS: usize = 3;
+/// Respawning players are placed at least this many steps away from all mobs if possible
+const SAFE_DISTANCE: usize = 5;
+/// How many random points are tried to find one far enough from mobs
+const SAFE_POINT_TRIES: usize = 20;
+
+type Objects = BTreeMap<u64, (ObjectType, Point)>;
+/// Timed effects of every player and the tick each wears off at
+type Effects = BTreeMap<u64, BTreeMap<PickupKind, u64>>;
+
 pub struct WorldState<'a> {
-    pub objects: Vec<(ObjectType, Point)>,
-    pub winner: Option<u64>,
+    pub tick: u64,
+    pub objects: ObjectsUpdate,
+    pub winners: &'a [u64],
     pub dead_players: Vec<u64>,
-    pub logs: &'a Vec<String>,
+    pub events: &'a [LoggedEvent],
+}
+
+struct TickSnapshot {
+    tick: u64,
+    objects: Objects,
+    /// What the players saw, their deltas are computed from the objects they saw
+    sights: BTreeMap<u64, Sight>,
+    event_cnt: usize,
 }
 
-#[derive(Default)]
+/// The whole simulation is driven by `rng`, so a world created with the same seed and
+/// receiving the same inputs in the same order always plays out the same
 pub struct World {
+    seed: u64,
+    rng: ChaCha8Rng,
     map_template: Map,
-    players: HashMap<u64, Point>,
-    mobs: HashMap<u64, Point>,
-    candies: HashMap<u64, Point>,
-    shots: HashMap<u64, Point>,
+    players: BTreeMap<u64, Point>,
+    mobs: BTreeMap<u64, Mob>,
+    candies: BTreeMap<u64, Point>,
+    pickups: BTreeMap<u64, Pickup>,
+    /// Doors of the maze that weren't opened yet
+    doors: BTreeMap<u64, Point>,
+    shots: BTreeMap<u64, Point>,
+    /// Players who fired the shots
+    shot_owners: BTreeMap<u64, u64>,
+    /// Collected pickups coming back at the tick
+    pickup_respawns: Vec<(u64, PickupKind)>,
+    effects: Effects,
+    /// Keys the players hold
+    keys: HashMap<u64, u32>,
 
-    winner: Option<u64>,
+    mode: GameMode,
+    /// Fog of war, players see everything without it
+    visibility: Option<Visibility>,
+    /// Set once the rules of the mode decided the game is over
+    finished: bool,
+    winners: Vec<u64>,
+    lives_config: LivesConfig,
+    /// Lives of the players, counting the one they are playing
+    lives: HashMap<u64, u32>,
+    /// Tick at which the players who lost a life come back
+    respawns: BTreeMap<u64, u64>,
+    /// Players who lost all their lives
     dead_players: Vec<u64>,
+    /// Players who left the maze through the exit
+    exited: Vec<u64>,
+    teams: HashMap<u64, Team>,
     player_names: HashMap<u64, String>,
-    logs: Vec<String>,
+    stats: HashMap<u64, PlayerStats>,
+    /// Tick each player joined at
+    joined_at: HashMap<u64, u64>,
+    /// Ticks it took the players to reach the exit
+    exit_ticks: HashMap<u64, u64>,
+    events: Vec<LoggedEvent>,
+
+    /// Actions waiting for the next tick, one per player is applied each tick, two with speed
+    inputs: BTreeMap<u64, VecDeque<PlayerAction>>,
+    /// Players in the order they joined, see `apply_inputs`
+    join_order: Vec<u64>,
+
+    /// Sequence number, bumped on every world tick and when players enter or leave
+    tick: u64,
+    /// Ticks the world moved so far, unlike `tick` not bumped by players entering or leaving.
+    /// Everything timed is measured in these.
+    ticks_played: u64,
+    history: VecDeque<TickSnapshot>,
+
+    recording_header: RecordingHeader,
+    /// Length of a tick, pickup effects and respawns are configured in seconds
+    tick_ms: u64,
+    /// Whether played ticks are kept until `take_recorded_ticks`
+    recording: bool,
+    recorded_ticks: Vec<TickRecord>,
+    /// Inputs and logs of the tick in progress
+    current_record: TickRecord,
+}
+
+fn has_effect(effects: &Effects, player_id: u64, kind: PickupKind) -> bool {
+    effects
+        .get(&player_id)
+        .is_some_and(|effects| effects.contains_key(&kind))
+}
+
+fn killer_player(killer: &Killer) -> Option<&PlayerRef> {
+    match killer {
+        Killer::Player { player } => Some(player),
+        Killer::Shot { shooter } => shooter.as_ref(),
+        Killer::Mob { .. } => None,
+    }
 }
 
 impl World {
-    pub fn new(maze_name: &str, mob_cnt: usize, candy_cnt: usize) -> Self {
-        let map = Map::new(maze_name);
+    pub fn new(
+        map: Map,
+        mobs: &[MobConfig],
+        candy_cnt: usize,
+        pickups: &[PickupConfig],
+        mode: GameMode,
+        lives: LivesConfig,
+    ) -> Self {
+        World::with_seed(map, mobs, candy_cnt, pickups, mode, lives, rand::random())
+    }
 
-        let mut used_points: HashSet<Point> = HashSet::new();
+    pub fn with_seed(
+        mut map: Map,
+        mobs: &[MobConfig],
+        candy_cnt: usize,
+        pickups: &[PickupConfig],
+        mode: GameMode,
+        lives: LivesConfig,
+        seed: u64,
+    ) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        // objects are placed as they spawn so that no two share a cell, those that don't fit
+        // into the maze are left out
+        let mut spawn_map = map.clone();
+        let spawn_at = |spawn_map: &mut Map, type_: ObjectType, rng: &mut ChaCha8Rng| {
+            let pos = spawn_map.random_empty_point(rng)?;
+            *spawn_map = std::mem::take(spawn_map).place_object(type_, &pos);
+            Some(pos)
+        };
 
         let candies = (0..candy_cnt)
-            .map(|_| loop {
-                let candy_pos = map.random_empty_point();
-                if used_points.insert(candy_pos) {
-                    break (rand::random(), candy_pos);
-                }
+            .map_while(|_| {
+                let candy_pos = spawn_at(&mut spawn_map, ObjectType::Candy, &mut rng)?;
+                Some((rng.gen(), candy_pos))
             })
             .collect();
 
-        let mobs = (0..mob_cnt)
-            .map(|_| loop {
-                let mob_pos = map.random_empty_point();
-                if used_points.insert(mob_pos) {
-                    break (rand::random(), mob_pos);
-                }
+        let mob_configs = mobs;
+        let mobs = mob_configs
+            .iter()
+            .flat_map(|config| std::iter::repeat_n(config, config.count))
+            .map_while(|config| {
+                let mob_pos = spawn_at(&mut spawn_map, ObjectType::Mob, &mut rng)?;
+                let mob = Mob::new(mob_pos, config, &spawn_map, &mut rng);
+                Some((rng.gen(), mob))
+            })
+            .collect();
+
+        let mut pickup_objects: BTreeMap<u64, Pickup> = pickups
+            .iter()
+            .flat_map(|config| std::iter::repeat_n(config, config.count))
+            .map_while(|config| {
+                let kind = config.kind;
+                let pos = spawn_at(&mut spawn_map, ObjectType::Pickup(kind), &mut rng)?;
+                Some((rng.gen(), Pickup { pos, kind }))
             })
             .collect();
 
-        World {
+        let recording_header = RecordingHeader {
+            maze: map.format(),
+            seed,
+            mobs: mob_configs.to_vec(),
+            candy_cnt,
+            mode,
+            lives,
+            pickups: pickups.to_vec(),
+            visibility: None,
+            time_limit: None,
+        };
+
+        // doors and pickups drawn in the maze become objects so that they can go away
+        let mut doors = BTreeMap::new();
+        for (type_, pos) in map.take_items() {
+            match type_ {
+                ObjectType::Pickup(kind) => {
+                    pickup_objects.insert(rng.gen(), Pickup { pos, kind });
+                }
+                _ => {
+                    doors.insert(rng.gen(), pos);
+                }
+            }
+        }
+
+        let mut world = World {
+            seed,
+            rng,
             map_template: map,
+            players: BTreeMap::new(),
             mobs,
             candies,
-            ..Default::default()
+            pickups: pickup_objects,
+            doors,
+            shots: BTreeMap::new(),
+            shot_owners: BTreeMap::new(),
+            pickup_respawns: Vec::new(),
+            effects: Effects::new(),
+            keys: HashMap::new(),
+            mode,
+            visibility: None,
+            finished: false,
+            winners: Vec::new(),
+            lives_config: lives,
+            lives: HashMap::new(),
+            respawns: BTreeMap::new(),
+            dead_players: Vec::new(),
+            exited: Vec::new(),
+            teams: HashMap::new(),
+            player_names: HashMap::new(),
+            stats: HashMap::new(),
+            joined_at: HashMap::new(),
+            exit_ticks: HashMap::new(),
+            events: Vec::new(),
+            inputs: BTreeMap::new(),
+            join_order: Vec::new(),
+            tick: 0,
+            ticks_played: 0,
+            history: VecDeque::new(),
+            recording_header,
+            tick_ms: DEFAULT_TICK_MS,
+            recording: false,
+            recorded_ticks: Vec::new(),
+            current_record: TickRecord::default(),
+        };
+        world.commit();
+        world
+    }
+
+    /// Limits what players see to the part of the maze around them
+    pub fn with_visibility(mut self, visibility: Option<Visibility>) -> Self {
+        self.visibility = visibility;
+        self.recording_header.visibility = visibility;
+        self
+    }
+
+    /// Tick length the pickup durations and respawn delays are converted with
+    pub fn with_tick_ms(mut self, tick_ms: u64) -> Self {
+        self.tick_ms = tick_ms;
+        self
+    }
+
+    /// Keeps the ticks played from now on for `take_recorded_ticks`
+    pub fn with_recording(mut self) -> Self {
+        self.recording = true;
+        self
+    }
+
+    /// Ends the game after `time_limit` ticks, if the mode has an outcome once time is up
+    pub fn with_time_limit(mut self, time_limit: Option<u64>) -> Self {
+        self.recording_header.time_limit = time_limit;
+        self
+    }
+
+    /// Fresh world with the same maze, mobs, candies, pickups, mode, lives, visibility, time limit
+    /// and tick length
+    pub fn rematch(&self, seed: u64) -> World {
+        // the template lacks the doors and pickups of the maze
+        let map = Map::from_template(&self.recording_header.maze)
+            .expect("The maze of an existing world is well formed");
+        World::with_seed(
+            map,
+            &self.recording_header.mobs,
+            self.recording_header.candy_cnt,
+            &self.recording_header.pickups,
+            self.mode,
+            self.lives_config,
+            seed,
+        )
+        .with_visibility(self.visibility)
+        .with_time_limit(self.recording_header.time_limit)
+        .with_tick_ms(self.tick_ms)
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    /// What the player sees of the maze, players who are out of the game for good see everything
+    pub fn sight(&self, player_id: u64) -> Sight {
+        let Some(visibility) = self.visibility else {
+            return Sight::Everything;
+        };
+        match self.players.get(&player_id) {
+            Some(pos) => Sight::Around {
+                from: *pos,
+                visibility,
+            },
+            None if self.respawns.contains_key(&player_id) => Sight::Nothing,
+            None => Sight::Everything,
+        }
+    }
+
+    fn rules(&self) -> &'static dyn GameRules {
+        self.mode.rules()
+    }
+
+    fn rules_view(&self) -> RulesView<'_> {
+        RulesView {
+            players: &self.join_order,
+            dead: &self.dead_players,
+            exited: &self.exited,
+            teams: &self.teams,
+            stats: &self.stats,
+            tick: self.ticks_played,
+            time_limit: self.recording_header.time_limit,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sequence number of the last change to the world
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Ticks the world moved so far
+    pub fn ticks_played(&self) -> u64 {
+        self.ticks_played
+    }
+
+    /// What the recording of the world starts from
+    pub fn recording_header(&self) -> &RecordingHeader {
+        &self.recording_header
+    }
+
+    /// Ticks recorded since the last call, they're only kept with `with_recording`
+    pub fn take_recorded_ticks(&mut self) -> Vec<TickRecord> {
+        std::mem::take(&mut self.recorded_ticks)
+    }
+
+    /// Applies the inputs of a recorded tick and advances the world
+    pub fn replay_tick(&mut self, tick: &TickRecord) {
+        for input in &tick.inputs {
+            match input {
+                RecordedInput::Join { name } => {
+                    self.spawn_player(name);
+                }
+                RecordedInput::Leave { player_id } => self.erase_player(*player_id),
+                RecordedInput::Action { player_id, action } => {
+                    self.queue_action(*player_id, *action)
+                }
+            }
         }
+        self.move_world();
+    }
+
+    pub fn winners(&self) -> &[u64] {
+        &self.winners
+    }
+
+    /// Name of a player who joined and hasn't left yet
+    pub fn player_name(&self, player_id: u64) -> Option<&str> {
+        self.player_names.get(&player_id).map(String::as_str)
+    }
+
+    /// Whether a player who joined and hasn't left yet goes by the name
+    pub fn has_player_named(&self, name: &str) -> bool {
+        self.player_names
+            .values()
+            .any(|player_name| player_name == name)
+    }
+
+    /// Where the player is, `None` while they are dead or waiting to respawn
+    pub fn player_position(&self, player_id: u64) -> Option<Point> {
+        self.players.get(&player_id).copied()
+    }
+
+    /// Lives the player has left, counting the one they are playing
+    pub fn lives(&self, player_id: u64) -> u32 {
+        self.lives.get(&player_id).copied().unwrap_or(0)
+    }
+
+    /// Ticks until the player who lost a life is back in the maze
+    pub fn respawn_in(&self, player_id: u64) -> Option<u64> {
+        self.respawns
+            .get(&player_id)
+            .map(|tick| tick.saturating_sub(self.ticks_played))
+    }
+
+    /// Timed effects of the player with the ticks left until they wear off
+    pub fn effects(&self, player_id: u64) -> Vec<(PickupKind, u64)> {
+        self.effects
+            .get(&player_id)
+            .into_iter()
+            .flatten()
+            .map(|(kind, ends_at)| (*kind, ends_at.saturating_sub(self.ticks_played)))
+            .collect()
+    }
+
+    pub fn keys(&self, player_id: u64) -> u32 {
+        self.keys.get(&player_id).copied().unwrap_or(0)
+    }
+
+    fn has_effect(&self, player_id: u64, kind: PickupKind) -> bool {
+        has_effect(&self.effects, player_id, kind)
+    }
+
+    /// What the player achieved in this world so far
+    pub fn player_stats(&self, player_id: u64) -> PlayerStats {
+        self.stats.get(&player_id).copied().unwrap_or_default()
+    }
+
+    /// Scores of the players in the game, best first
+    pub fn scores(&self) -> Vec<PlayerScore> {
+        let mut scores: Vec<PlayerScore> = self
+            .player_names
+            .iter()
+            .map(|(player_id, name)| {
+                let stats = self.player_stats(*player_id);
+                let exit_ticks = self.exit_ticks.get(player_id).copied();
+                PlayerScore {
+                    player_id: *player_id,
+                    name: name.clone(),
+                    stats,
+                    exit_ticks,
+                    dead: self.dead_players.contains(player_id),
+                    team: self.teams.get(player_id).copied(),
+                    points: score::points(&stats, exit_ticks),
+                }
+            })
+            .collect();
+        scores.sort_by(|a, b| b.points.cmp(&a.points).then_with(|| a.name.cmp(&b.name)));
+        scores
+    }
+
+    fn stats_mut(&mut self, player_id: u64) -> &mut PlayerStats {
+        self.stats.entry(player_id).or_default()
+    }
+
+    fn update_stats(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::PlayerJoined { player } => {
+                self.stats_mut(player.id).games_played = 1;
+                self.joined_at.insert(player.id, self.ticks_played);
+            }
+            GameEvent::CandyCollected { player, .. } => self.stats_mut(player.id).candies += 1,
+            GameEvent::MobKilled { by, .. } => {
+                if let Some(killer) = killer_player(by) {
+                    self.stats_mut(killer.id).mob_kills += 1;
+                }
+            }
+            GameEvent::PlayerKilled { player, by } => {
+                self.stats_mut(player.id).deaths += 1;
+                if let Some(killer) = killer_player(by).filter(|killer| killer.id != player.id) {
+                    self.stats_mut(killer.id).player_kills += 1;
+                }
+            }
+            GameEvent::PlayerExited { player } => {
+                let joined_at = self.joined_at.get(&player.id).copied().unwrap_or(0);
+                self.exit_ticks
+                    .insert(player.id, self.ticks_played + 1 - joined_at);
+            }
+            GameEvent::PlayerWon { player } => self.stats_mut(player.id).wins += 1,
+            GameEvent::PlayerLeft { .. }
+            | GameEvent::PlayerRespawned { .. }
+            | GameEvent::PickupCollected { .. }
+            | GameEvent::EffectEnded { .. }
+            | GameEvent::DoorOpened { .. }
+            | GameEvent::ExitBlocked { .. } => {}
+        }
+    }
+
+    /// Killer of a shot, its shooter is known only if they are still in the game
+    fn shot_by(&self, shooter: Option<u64>) -> Killer {
+        let shooter = shooter
+            .filter(|shooter| self.player_names.contains_key(shooter))
+            .map(|shooter| self.player_ref(shooter));
+        Killer::Shot { shooter }
+    }
+
+    fn player_ref(&self, player_id: u64) -> PlayerRef {
+        PlayerRef {
+            id: player_id,
+            name: self
+                .player_names
+                .get(&player_id)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Takes a life of the player, they respawn after a while unless it was their last one
+    fn kill_player(&mut self, player_id: u64, by: Killer) {
+        // players drawn in the maze rather than joined only stand in the way
+        if !self.player_names.contains_key(&player_id) {
+            return;
+        }
+        self.players.remove(&player_id);
+        self.effects.remove(&player_id);
+        // the player may be hit more than once in the same tick
+        if self.dead_players.contains(&player_id) || self.respawns.contains_key(&player_id) {
+            return;
+        }
+        let lives = self.lives.entry(player_id).or_insert(1);
+        *lives = lives.saturating_sub(1);
+        if *lives > 0 {
+            let respawn_at = self.ticks_played + 1 + self.lives_config.respawn_ticks;
+            self.respawns.insert(player_id, respawn_at);
+        } else {
+            self.dead_players.push(player_id);
+        }
+        let player = self.player_ref(player_id);
+        self.emit(GameEvent::PlayerKilled { player, by });
+    }
+
+    /// Puts the players whose respawn delay is over back into the maze
+    fn respawn_players(&mut self) {
+        let due: Vec<u64> = self
+            .respawns
+            .iter()
+            .filter(|(_, respawn_at)| **respawn_at <= self.ticks_played)
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        for player_id in due {
+            // in a full maze the players wait until there is room again
+            let Some(pos) = self.safe_point() else {
+                break;
+            };
+            self.respawns.remove(&player_id);
+            self.players.insert(player_id, pos);
+            let player = self.player_ref(player_id);
+            let lives = self.lives(player_id);
+            self.emit(GameEvent::PlayerRespawned { player, lives });
+        }
+    }
+
+    /// Random empty point at least `SAFE_DISTANCE` steps away from all mobs, or the farthest
+    /// one of the tried points if there is no such point. `None` if the maze is full.
+    fn safe_point(&mut self) -> Option<Point> {
+        let map = self.fill_map(self.map_template.clone());
+        let mob_distance = |point: &Point| {
+            self.mobs
+                .values()
+                .map(|mob| mob.pos.distance(point))
+                .min()
+                .unwrap_or(usize::MAX)
+        };
+        let mut best = map.random_empty_point(&mut self.rng)?;
+        for _ in 1..SAFE_POINT_TRIES {
+            if mob_distance(&best) >= SAFE_DISTANCE {
+                break;
+            }
+            let point = map.random_empty_point(&mut self.rng)?;
+            if mob_distance(&point) > mob_distance(&best) {
+                best = point;
+            }
+        }
+        Some(best)
+    }
+
+    /// The rules of the mode decided who won, possibly nobody
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Asks the rules whether the game is over and announces the winners
+    fn check_finished(&mut self) {
+        if self.finished {
+            return;
+        }
+        let Some(winners) = self.rules().outcome(&self.rules_view()) else {
+            return;
+        };
+        self.finished = true;
+        for player_id in &winners {
+            let player = self.player_ref(*player_id);
+            self.emit(GameEvent::PlayerWon { player });
+        }
+        self.winners = winners;
     }
 
     pub fn can_play(&self, player_id: u64) -> bool {
-        self.winner.is_none() && !self.dead_players.contains(&player_id)
+        !self.is_finished()
+            && !self.dead_players.contains(&player_id)
+            && !self.exited.contains(&player_id)
+    }
+
+    pub fn get_state(&self) -> WorldState<'_> {
+        self.get_state_since(None, None)
     }
 
-    pub fn get_state(&self) -> WorldState {
+    /// Returns only the changes made after `since` tick, or a full snapshot if the
+    /// tick is unknown or already dropped from the history. Only objects in the `viewer`'s sight
+    /// are included, all of them without a viewer.
+    pub fn get_state_since(&self, since: Option<u64>, viewer: Option<u64>) -> WorldState<'_> {
+        let base = since.and_then(|since| self.history.iter().find(|snap| snap.tick == since));
+        let sight = viewer.map_or(Sight::Everything, |player_id| self.sight(player_id));
+        let current = self.object_positions();
+        let current = self.objects_in_sight(&current, &sight);
+
+        let (objects, events) = match base {
+            Some(base) => {
+                // players who weren't in the game yet saw nothing
+                let base_sight = match viewer {
+                    Some(player_id) => base.sights.get(&player_id).copied(),
+                    None => Some(Sight::Everything),
+                };
+                let base_objects =
+                    self.objects_in_sight(&base.objects, &base_sight.unwrap_or(Sight::Nothing));
+                let changed = current
+                    .iter()
+                    .filter(|(id, obj)| base_objects.get(id) != Some(obj))
+                    .map(|(id, (type_, pos))| (*id, *type_, *pos))
+                    .collect();
+                let removed = base_objects
+                    .keys()
+                    .filter(|id| !current.contains_key(id))
+                    .copied()
+                    .collect();
+                (
+                    ObjectsUpdate::Delta { changed, removed },
+                    &self.events[base.event_cnt..],
+                )
+            }
+            None => (
+                ObjectsUpdate::Snapshot {
+                    objects: current
+                        .iter()
+                        .map(|(id, (type_, pos))| (*id, *type_, *pos))
+                        .collect(),
+                },
+                &self.events[self.events.len().saturating_sub(SNAPSHOT_EVENT_CNT)..],
+            ),
+        };
+
         WorldState {
-            objects: self.object_positions(),
-            winner: self.winner,
+            tick: self.tick,
+            objects,
+            winners: &self.winners,
             dead_players: self.dead_players.clone(),
-            logs: &self.logs,
+            events,
         }
     }
 
-    fn object_positions(&self) -> Vec<(ObjectType, Point)> {
-        let mut positions: Vec<(ObjectType, Point)> = Vec::new();
-        for player in self.players.values() {
-            positions.push((ObjectType::Player(player.dir), *player));
+    fn objects_in_sight<'a>(&self, objects: &'a Objects, sight: &Sight) -> Cow<'a, Objects> {
+        match sight {
+            Sight::Everything => Cow::Borrowed(objects),
+            _ => Cow::Owned(
+                objects
+                    .iter()
+                    .filter(|(_, (_, pos))| sight.can_see(&self.map_template, pos))
+                    .map(|(id, obj)| (*id, *obj))
+                    .collect(),
+            ),
         }
-        for mob in self.mobs.values() {
-            positions.push((ObjectType::Mob, *mob));
+    }
+
+    fn object_positions(&self) -> Objects {
+        let mut positions = Objects::new();
+        for (id, player) in &self.players {
+            positions.insert(*id, (ObjectType::Player(player.dir), *player));
+        }
+        for (id, mob) in &self.mobs {
+            positions.insert(*id, (ObjectType::Mob, mob.pos));
         }
-        for candy in self.candies.values() {
-            positions.push((ObjectType::Candy, *candy));
+        for (id, candy) in &self.candies {
+            positions.insert(*id, (ObjectType::Candy, *candy));
         }
-        for shot in self.shots.values() {
-            positions.push((ObjectType::Shot(shot.dir), *shot));
+        for (id, pickup) in &self.pickups {
+            positions.insert(*id, (ObjectType::Pickup(pickup.kind), pickup.pos));
+        }
+        for (id, door) in &self.doors {
+            positions.insert(*id, (ObjectType::Door, *door));
+        }
+        for (id, shot) in &self.shots {
+            positions.insert(*id, (ObjectType::Shot(shot.dir), *shot));
         }
         positions
     }
 
+    /// Bumps the tick and remembers the resulting objects for later deltas
+    fn commit(&mut self) {
+        self.tick += 1;
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(TickSnapshot {
+            tick: self.tick,
+            objects: self.object_positions(),
+            sights: self
+                .join_order
+                .iter()
+                .map(|player_id| (*player_id, self.sight(*player_id)))
+                .collect(),
+            event_cnt: self.events.len(),
+        });
+    }
+
+    /// Maze with all objects placed in it
+    pub fn get_map(&self) -> Map {
+        self.fill_map(self.map_template.clone())
+    }
+
     pub fn width(&self) -> usize {
         self.map_template.width()
     }
@@ -111,9 +746,15 @@ impl World {
         self.map_template.height()
     }
 
-    fn log(&mut self, msg: String) {
-        self.logs
-            .push(format!("{}: {}", Local::now().format("%H:%M:%S"), msg));
+    fn emit(&mut self, event: GameEvent) {
+        self.update_stats(&event);
+        self.current_record.events.push(event.clone());
+        self.events.push(LoggedEvent {
+            // the tick being computed, it's committed once all events of it are emitted
+            tick: self.tick + 1,
+            time: Local::now().format("%H:%M:%S").to_string(),
+            event,
+        });
     }
 
     fn fill_map(&self, mut map: Map) -> Map {
@@ -121,64 +762,249 @@ impl World {
             map = map.place_object_with_id(*id, ObjectType::Player(player.dir), player);
         }
         for (id, mob) in &self.mobs {
-            map = map.place_object_with_id(*id, ObjectType::Mob, mob);
+            map = map.place_object_with_id(*id, ObjectType::Mob, &mob.pos);
         }
         for (id, candy) in &self.candies {
             map = map.place_object_with_id(*id, ObjectType::Candy, candy);
         }
+        for (id, pickup) in &self.pickups {
+            map = map.place_object_with_id(*id, ObjectType::Pickup(pickup.kind), &pickup.pos);
+        }
+        for (id, door) in &self.doors {
+            map = map.place_object_with_id(*id, ObjectType::Door, door);
+        }
         for (id, shot) in &self.shots {
             map = map.place_object_with_id(*id, ObjectType::Shot(shot.dir), shot);
         }
         map
     }
 
+    /// Queues the action to be applied on the next tick
+    pub fn queue_action(&mut self, player_id: u64, action: PlayerAction) {
+        let queue = self.inputs.entry(player_id).or_default();
+        if queue.len() < MAX_QUEUED_ACTIONS {
+            queue.push_back(action);
+        }
+    }
+
+    /// Applies one queued action of every player. Simultaneous moves are resolved in join
+    /// order, rotated by one every tick so that nobody always gets to move first.
+    fn apply_inputs(&mut self) {
+        let player_cnt = self.join_order.len();
+        if player_cnt == 0 {
+            return;
+        }
+        let first = self.tick as usize % player_cnt;
+        let order: Vec<u64> = self
+            .join_order
+            .iter()
+            .cycle()
+            .skip(first)
+            .take(player_cnt)
+            .copied()
+            .collect();
+
+        for player_id in order {
+            let action_cnt = if self.has_effect(player_id, PickupKind::Speed) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..action_cnt {
+                // the player may have died or left the maze by the previous action
+                if !self.players.contains_key(&player_id) {
+                    self.inputs.remove(&player_id);
+                    break;
+                }
+                let Some(action) = self
+                    .inputs
+                    .get_mut(&player_id)
+                    .and_then(VecDeque::pop_front)
+                else {
+                    break;
+                };
+                self.current_record
+                    .inputs
+                    .push(RecordedInput::Action { player_id, action });
+                match action {
+                    PlayerAction::Shoot => self.player_shoot(player_id),
+                    PlayerAction::Move(dir) => self.move_player(player_id, dir),
+                }
+            }
+        }
+    }
+
     pub fn move_world(&mut self) {
+        self.respawn_players();
+        self.respawn_pickups();
+        self.apply_inputs();
+
         let mut map = self.fill_map(self.map_template.clone());
 
-        map = self.move_random_mob(map);
+        map = self.move_mobs(map);
         self.move_shots(map);
+        self.expire_effects();
+        // the rules see this tick as played, so that a time limit ends the game on its last tick
+        self.ticks_played += 1;
+        self.check_finished();
+        self.commit();
+
+        let record = std::mem::take(&mut self.current_record);
+        if self.recording {
+            self.recorded_ticks.push(record);
+        }
     }
 
-    pub fn move_random_mob(&mut self, mut map: Map) -> Map {
-        let mob_cnt = self.mobs.len();
-        if mob_cnt == 0 {
-            return map;
+    /// Places the collected pickups whose respawn delay is over back into the maze
+    fn respawn_pickups(&mut self) {
+        let tick = self.ticks_played;
+        let (due, waiting) = std::mem::take(&mut self.pickup_respawns)
+            .into_iter()
+            .partition(|(respawn_at, _)| *respawn_at <= tick);
+        self.pickup_respawns = waiting;
+        let due: Vec<(u64, PickupKind)> = due;
+        if due.is_empty() {
+            return;
         }
-        let random_mob_id = *self
-            .mobs
-            .keys()
-            .nth(rand::random::<usize>() % mob_cnt)
-            .unwrap();
-        let mob_pos = self.mobs.remove(&random_mob_id).unwrap();
+        let mut map = self.fill_map(self.map_template.clone());
+        for (respawn_at, kind) in due {
+            let Some(pos) = map.random_empty_point(&mut self.rng) else {
+                // tried again next tick, the maze may have room by then
+                self.pickup_respawns.push((respawn_at, kind));
+                continue;
+            };
+            let pickup_id = self.rng.gen();
+            map = map.place_object_with_id(pickup_id, ObjectType::Pickup(kind), &pos);
+            self.pickups.insert(pickup_id, Pickup { pos, kind });
+        }
+    }
 
-        let new_pos = mob_pos.turn_and_step(rand::random());
-        let collided_obj = map.get_object(&new_pos);
-        match collided_obj.type_ {
-            ObjectType::Empty => {
-                map.swap_objects(&mob_pos, &new_pos);
-                self.mobs.insert(random_mob_id, new_pos);
-            }
-            ObjectType::Player(_) => {
-                self.log(format!(
-                    "{} killed by mob",
-                    self.player_names[&collided_obj.id]
-                ));
-                self.players.remove(&collided_obj.id);
-                self.dead_players.push(collided_obj.id);
-                map.swap_objects(&mob_pos, &new_pos);
-                map.clear_object(&mob_pos);
-                self.mobs.insert(random_mob_id, new_pos);
+    /// How the pickups of the kind behave, those drawn in the maze may have no config
+    fn pickup_config(&self, kind: PickupKind) -> PickupConfig {
+        self.recording_header
+            .pickups
+            .iter()
+            .find(|config| config.kind == kind)
+            .copied()
+            .unwrap_or_else(|| PickupConfig::new(kind, 0))
+    }
+
+    fn collect_pickup(&mut self, player_id: u64, pickup_id: u64, kind: PickupKind) {
+        self.pickups.remove(&pickup_id);
+        let config = self.pickup_config(kind);
+        if kind.is_timed() {
+            // picking up an active effect again restarts it
+            let ends_at = self.ticks_played + 1 + config.duration_ticks(self.tick_ms);
+            self.effects
+                .entry(player_id)
+                .or_default()
+                .insert(kind, ends_at);
+        } else {
+            *self.keys.entry(player_id).or_default() += 1;
+        }
+        if let Some(respawn_ticks) = config.respawn_ticks(self.tick_ms) {
+            self.pickup_respawns
+                .push((self.ticks_played + 1 + respawn_ticks, kind));
+        }
+        let player = self.player_ref(player_id);
+        self.emit(GameEvent::PickupCollected { player, kind });
+    }
+
+    /// Removes the effects that wore off by the end of the tick being computed
+    fn expire_effects(&mut self) {
+        let tick = self.ticks_played + 1;
+        let mut ended = Vec::new();
+        for (player_id, effects) in &mut self.effects {
+            effects.retain(|kind, ends_at| {
+                let active = *ends_at > tick;
+                if !active {
+                    ended.push((*player_id, *kind));
+                }
+                active
+            });
+        }
+        self.effects.retain(|_, effects| !effects.is_empty());
+        for (player_id, kind) in ended {
+            let player = self.player_ref(player_id);
+            self.emit(GameEvent::EffectEnded { player, kind });
+        }
+    }
+
+    /// Moves every mob whose turn it is, each according to its behavior, unless a player froze
+    /// them
+    pub fn move_mobs(&mut self, mut map: Map) -> Map {
+        let frozen = self
+            .effects
+            .values()
+            .any(|effects| effects.contains_key(&PickupKind::Freeze));
+        if frozen {
+            return map;
+        }
+        let mob_ids: Vec<u64> = self.mobs.keys().copied().collect();
+        for mob_id in mob_ids {
+            // the mob may have been killed by another one's move
+            let Some(mut mob) = self.mobs.remove(&mob_id) else {
+                continue;
+            };
+            if !mob.ready() {
+                self.mobs.insert(mob_id, mob);
+                continue;
             }
-            _ => {
-                self.mobs.insert(random_mob_id, mob_pos);
+            let view = MobView {
+                map: &map,
+                players: &self.players,
+                candies: &self.candies,
+            };
+            let Some(dir) = mob
+                .kind
+                .behavior()
+                .next_step(&mut mob, &view, &mut self.rng)
+            else {
+                self.mobs.insert(mob_id, mob);
+                continue;
+            };
+
+            let mob_pos = mob.pos;
+            let new_pos = mob_pos.turn_and_step(dir);
+            let collided_obj = map.get_object(&new_pos);
+            match collided_obj.type_ {
+                // the same cells mobs find their paths through
+                type_ if !can_enter(type_) => {}
+                ObjectType::Player(_) if self.has_effect(collided_obj.id, PickupKind::Shield) => {}
+                ObjectType::Player(_) => {
+                    self.kill_player(collided_obj.id, Killer::Mob { kind: mob.kind });
+                    map.swap_objects(&mob_pos, &new_pos);
+                    map.clear_object(&mob_pos);
+                    mob.pos = new_pos;
+                }
+                _ => {
+                    map.swap_objects(&mob_pos, &new_pos);
+                    mob.pos = new_pos;
+                }
             }
+            self.mobs.insert(mob_id, mob);
         }
         map
     }
 
     pub fn move_shots(&mut self, mut map: Map) -> Map {
-        let mut new_logs: Vec<String> = Vec::new();
-        self.shots.retain(|_, shot| {
+        // (kind, shooter) of the killed mobs and (victim, shooter) of the killed players
+        let mut mob_kills: Vec<(MobKind, Option<u64>)> = Vec::new();
+        let mut player_kills: Vec<(u64, Option<u64>)> = Vec::new();
+        let rules = self.rules();
+        // borrows the fields one by one, the shots are modified meanwhile
+        let view = RulesView {
+            players: &self.join_order,
+            dead: &self.dead_players,
+            exited: &self.exited,
+            teams: &self.teams,
+            stats: &self.stats,
+            tick: self.ticks_played,
+            time_limit: self.recording_header.time_limit,
+        };
+        let effects = &self.effects;
+        self.shots.retain(|shot_id, shot| {
+            let shooter = self.shot_owners.get(shot_id).copied();
             let new_pos = shot.step();
             let collider_obj = map.get_object(&new_pos);
             match collider_obj.type_ {
@@ -188,21 +1014,22 @@ impl World {
                     true
                 }
                 ObjectType::Mob => {
-                    new_logs.push("Mob killed by stray shot".to_string());
-                    self.mobs.remove(&collider_obj.id);
+                    if let Some(mob) = self.mobs.remove(&collider_obj.id) {
+                        mob_kills.push((mob.kind, shooter));
+                    }
                     map.clear_object(shot);
                     map.clear_object(&new_pos);
                     false
                 }
                 ObjectType::Player(_) => {
-                    new_logs.push(format!(
-                        "{} killed by stray shot",
-                        self.player_names[&collider_obj.id]
-                    ));
-                    self.players.remove(&collider_obj.id);
-                    self.dead_players.push(collider_obj.id);
+                    let victim = collider_obj.id;
+                    if rules.can_hurt(&view, shooter, victim)
+                        && !has_effect(effects, victim, PickupKind::Shield)
+                    {
+                        player_kills.push((victim, shooter));
+                        map.clear_object(&new_pos);
+                    }
                     map.clear_object(shot);
-                    map.clear_object(&new_pos);
                     false
                 }
                 _ => {
@@ -211,24 +1038,43 @@ impl World {
                 }
             }
         });
-        for log in new_logs {
-            self.log(log);
+        self.shot_owners
+            .retain(|shot_id, _| self.shots.contains_key(shot_id));
+        for (kind, shooter) in mob_kills {
+            let by = self.shot_by(shooter);
+            self.emit(GameEvent::MobKilled { kind, by });
+        }
+        for (victim, shooter) in player_kills {
+            let by = self.shot_by(shooter);
+            self.kill_player(victim, by);
         }
         map
     }
 
-    pub fn spawn_player(&mut self, player_name: &str) -> u64 {
+    /// Adds the player at a random place, `None` if there is no room left in the maze
+    pub fn spawn_player(&mut self, player_name: &str) -> Option<u64> {
+        // recorded even if the player doesn't fit, the replay then fails the same way
+        self.current_record.inputs.push(RecordedInput::Join {
+            name: player_name.to_string(),
+        });
         let map = self.fill_map(self.map_template.clone());
 
-        let player = map.random_empty_point();
-        let player_id = rand::random();
+        let player = map.random_empty_point(&mut self.rng)?;
+        let player_id = self.rng.gen();
         self.players.insert(player_id, player);
         self.player_names.insert(player_id, player_name.to_string());
-        self.log(format!("Player {} entered world", player_name));
-        player_id
+        self.lives.insert(player_id, self.lives_config.lives.max(1));
+        if let Some(team) = self.rules().assign_team(&self.rules_view()) {
+            self.teams.insert(player_id, team);
+        }
+        self.join_order.push(player_id);
+        let player = self.player_ref(player_id);
+        self.emit(GameEvent::PlayerJoined { player });
+        self.commit();
+        Some(player_id)
     }
 
-    pub fn move_player(&mut self, player_id: u64, direction: Direction) {
+    fn move_player(&mut self, player_id: u64, direction: Direction) {
         let map = self.fill_map(self.map_template.clone());
 
         let mut player = self
@@ -242,75 +1088,161 @@ impl World {
             ObjectType::Empty => {
                 player = new_pos;
             }
-            ObjectType::Exit => {
+            ObjectType::Exit if self.rules().exit_open() => {
+                let event_player = self.player_ref(player_id);
                 if !self.candies.is_empty() {
-                    self.log(format!(
-                        "You need to collect {} more candies, {}",
-                        self.candies.len(),
-                        self.player_names[&player_id]
-                    ));
+                    self.emit(GameEvent::ExitBlocked {
+                        player: event_player,
+                        remaining: self.candies.len(),
+                    });
                 } else {
-                    self.log(format!(
-                        "Player {} won the game",
-                        self.player_names[&player_id]
-                    ));
-                    player = new_pos; // remove player
-                    self.winner = Some(player_id);
+                    // the player leaves the maze, who won is up to the rules
+                    self.emit(GameEvent::PlayerExited {
+                        player: event_player,
+                    });
+                    self.exited.push(player_id);
+                    return;
                 }
             }
-            ObjectType::Mob => {
-                self.log(format!("{} killed by mob", self.player_names[&player_id]));
-                self.players.remove(&player_id);
-                self.dead_players.push(player_id);
-                return;
+            ObjectType::Mob if !self.has_effect(player_id, PickupKind::Shield) => {
+                // mobs drawn in the maze rather than spawned only stand in the way
+                if let Some(mob) = self.mobs.get(&collider_obj.id) {
+                    let kind = mob.kind;
+                    self.kill_player(player_id, Killer::Mob { kind });
+                    return;
+                }
             }
             ObjectType::Candy => {
                 player = new_pos;
                 self.candies.remove(&collider_obj.id);
-                self.log(format!("{} candies left", self.candies.len()));
+                self.emit(GameEvent::CandyCollected {
+                    player: self.player_ref(player_id),
+                    remaining: self.candies.len(),
+                });
+            }
+            ObjectType::Pickup(kind) => {
+                player = new_pos;
+                self.collect_pickup(player_id, collider_obj.id, kind);
+            }
+            ObjectType::Door if self.keys(player_id) > 0 => {
+                player = new_pos;
+                self.doors.remove(&collider_obj.id);
+                *self.keys.entry(player_id).or_default() -= 1;
+                self.emit(GameEvent::DoorOpened {
+                    player: self.player_ref(player_id),
+                });
             }
             _ => {}
         }
         self.players.insert(player_id, player);
     }
 
-    pub fn player_shoot(&mut self, player_id: u64) {
+    /// Fires forward, with multi-shot also to both sides
+    fn player_shoot(&mut self, player_id: u64) {
         let map = self.fill_map(self.map_template.clone());
 
-        let player = self
+        let player = *self
             .players
             .get(&player_id)
             .unwrap_or_else(|| panic!("Player {} not found", player_id));
-        let pos = player.step();
+        let mut directions = vec![player.dir];
+        if self.has_effect(player_id, PickupKind::MultiShot) {
+            directions.extend(player.dir.sides());
+        }
+        for dir in directions {
+            let mut pos = player.turn_and_step(dir);
+            pos.dir = dir;
+            self.fire(player_id, pos, &map);
+        }
+    }
 
+    /// Puts a shot at `pos` flying in its direction, whatever is right there is hit at once
+    fn fire(&mut self, player_id: u64, pos: Point, map: &Map) {
         let collider_obj = map.get_object(&pos);
         match collider_obj.type_ {
             ObjectType::Empty => {
-                let shot_id = rand::random();
+                let shot_id = self.rng.gen();
                 self.shots.insert(shot_id, pos);
+                self.shot_owners.insert(shot_id, player_id);
             }
             ObjectType::Mob => {
-                self.mobs.remove(&collider_obj.id);
+                if let Some(mob) = self.mobs.remove(&collider_obj.id) {
+                    let player = self.player_ref(player_id);
+                    self.emit(GameEvent::MobKilled {
+                        kind: mob.kind,
+                        by: Killer::Player { player },
+                    });
+                }
             }
-            ObjectType::Player(_) => {
-                self.log(format!(
-                    "{} killed {}",
-                    self.player_names[&player_id], self.player_names[&collider_obj.id]
-                ));
-                self.players.remove(&collider_obj.id);
-                self.dead_players.push(collider_obj.id);
+            ObjectType::Player(_)
+                if self
+                    .rules()
+                    .can_hurt(&self.rules_view(), Some(player_id), collider_obj.id)
+                    && !self.has_effect(collider_obj.id, PickupKind::Shield) =>
+            {
+                let player = self.player_ref(player_id);
+                self.kill_player(collider_obj.id, Killer::Player { player });
             }
             _ => (),
         }
     }
 
     pub fn erase_player(&mut self, player_id: u64) {
-        self.log(format!(
-            "Player {} left the game",
-            self.player_names[&player_id]
-        ));
+        self.current_record
+            .inputs
+            .push(RecordedInput::Leave { player_id });
+        let player = self.player_ref(player_id);
+        self.emit(GameEvent::PlayerLeft { player });
         self.players.remove(&player_id);
         self.player_names.remove(&player_id);
         self.dead_players.retain(|player| *player != player_id);
+        self.exited.retain(|player| *player != player_id);
+        self.lives.remove(&player_id);
+        self.respawns.remove(&player_id);
+        self.effects.remove(&player_id);
+        self.keys.remove(&player_id);
+        self.inputs.remove(&player_id);
+        self.join_order.retain(|player| *player != player_id);
+        self.commit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A world with a single free cell, right of which `drawn` stands
+    fn world_next_to(drawn: char) -> (World, u64) {
+        let map = Map::from_template(&format!("█████\n█ {drawn}X█\n█████")).unwrap();
+        let mut world = World::with_seed(
+            map,
+            &[],
+            0,
+            &[],
+            GameMode::Classic,
+            LivesConfig::default(),
+            1,
+        );
+        let player_id = world.spawn_player("player").unwrap();
+        (world, player_id)
+    }
+
+    #[test]
+    fn drawn_mobs_only_block_the_way() {
+        let (mut world, player_id) = world_next_to('*');
+        world.queue_action(player_id, PlayerAction::Move(Direction::Right));
+        world.move_world();
+        assert!(world.can_play(player_id));
+    }
+
+    #[test]
+    fn drawn_players_cant_be_shot() {
+        let (mut world, player_id) = world_next_to('<');
+        world.queue_action(player_id, PlayerAction::Move(Direction::Right));
+        world.queue_action(player_id, PlayerAction::Shoot);
+        world.move_world();
+        world.move_world();
+        assert!(world.can_play(player_id));
+        assert!(world.get_state().dead_players.is_empty());
     }
 }
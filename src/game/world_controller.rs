@@ -1,14 +1,41 @@
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::{sleep, Duration};
+use tokio::sync::{watch, Mutex};
+use tokio::time::{interval, Duration};
 
 use crate::game::world::World;
 
-pub fn run_world(world: Arc<Mutex<World>>) {
+pub const DEFAULT_TICK_MS: u64 = 100;
+/// Shortest tick the world loop keeps up with
+pub const MIN_TICK_MS: u64 = 10;
+/// Longest tick, beyond it games wouldn't be playable anyway
+pub const MAX_TICK_MS: u64 = 10_000;
+/// How long games count down once all players in the lobby are ready
+pub const DEFAULT_COUNTDOWN_S: u64 = 3;
+pub const DEFAULT_LIVES: u32 = 3;
+/// How long players wait to come back into the maze after losing a life
+pub const DEFAULT_RESPAWN_S: u64 = 3;
+/// How long deathmatches last
+pub const DEFAULT_TIME_LIMIT_S: u64 = 120;
+
+/// Spawns the world loop ticking every `tick` until the world is finished, the returned
+/// receiver is notified after every tick. Ticks are scheduled on a fixed interval, so a slow
+/// tick doesn't delay the following ones.
+pub fn run_world(world: Arc<Mutex<World>>, tick: Duration) -> watch::Receiver<()> {
+    let (tick_tx, tick_rx) = watch::channel(());
     tokio::spawn(async move {
+        let mut ticker = interval(tick);
         loop {
-            world.lock().await.move_world();
-            sleep(Duration::from_millis(100)).await;
+            ticker.tick().await;
+            let finished = {
+                let mut world = world.lock().await;
+                world.move_world();
+                world.is_finished()
+            };
+            let _ = tick_tx.send(());
+            if finished {
+                break;
+            }
         }
     });
+    tick_rx
 }
@@ -1,19 +1,30 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use std::io::{stdin, stdout, Write};
+use std::io::stdin;
+use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Instant;
 use termion::event::Key;
 use termion::input::TermRead;
-use termion::raw::IntoRawMode;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
 
-mod game;
-use game::map::Direction;
-use game::world::World;
-use game::world_controller::run_world;
+use candy_game::client::events::describe_logged;
+use candy_game::client::renderer::Renderer;
+use candy_game::client::theme::{PlayerSlots, Theme};
+use candy_game::client::viewport::Viewport;
+use candy_game::game::api::PlayerAction;
+use candy_game::game::map::{Direction, Map};
+use candy_game::game::maze_gen::{MazeGenerator, MazeSpec};
+use candy_game::game::maze_loader::{check_capacity, MazeLibrary};
+use candy_game::game::mob::{mob_count, MobConfig, MobKind};
+use candy_game::game::pickup::{pickup_count, PickupConfig, PickupKind};
+use candy_game::game::rules::{GameMode, LivesConfig};
+use candy_game::game::world::World;
+use candy_game::game::world_controller::{
+    run_world, DEFAULT_LIVES, DEFAULT_RESPAWN_S, DEFAULT_TICK_MS, MAX_TICK_MS, MIN_TICK_MS,
+};
 
 async fn read_player_movement(world: Arc<Mutex<World>>, player_id: u64) -> Result<()> {
     let mut keys = stdin().keys();
@@ -31,11 +42,11 @@ async fn read_player_movement(world: Arc<Mutex<World>>, player_id: u64) -> Resul
             Key::Esc | Key::Char('q') | Key::Ctrl('c') => {
                 return Ok(());
             }
-            Key::Left => world.move_player(player_id, Direction::Left),
-            Key::Right => world.move_player(player_id, Direction::Right),
-            Key::Up => world.move_player(player_id, Direction::Up),
-            Key::Down => world.move_player(player_id, Direction::Down),
-            Key::Char(' ') => world.player_shoot(player_id),
+            Key::Left => world.queue_action(player_id, PlayerAction::Move(Direction::Left)),
+            Key::Right => world.queue_action(player_id, PlayerAction::Move(Direction::Right)),
+            Key::Up => world.queue_action(player_id, PlayerAction::Move(Direction::Up)),
+            Key::Down => world.queue_action(player_id, PlayerAction::Move(Direction::Down)),
+            Key::Char(' ') => world.queue_action(player_id, PlayerAction::Shoot),
             _ => (),
         };
         prev_press = Instant::now();
@@ -47,58 +58,58 @@ async fn show_map_loop(
     world: Arc<Mutex<World>>,
     stop: Arc<AtomicBool>,
     player_id: u64,
+    theme: Theme,
 ) -> Result<()> {
-    let mut stdout = stdout().into_raw_mode()?;
+    let mut renderer = Renderer::new()?;
+    let mut player_slots = PlayerSlots::default();
+    let mut viewport = Viewport::default();
 
     while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+        let mut frame = renderer.frame();
         {
             let world = world.lock().await;
 
             let world_state = world.get_state();
-            let map = world.get_map_template().place_objects(world_state.objects);
-
-            write!(
-                stdout,
-                "{}{}{}",
-                termion::clear::All,
-                termion::cursor::Goto(1, 1),
-                map.format()
-            )?;
-
-            for (i, log) in world_state
-                .logs
+            let map = world.get_map();
+
+            // large mazes don't fit on the terminal, the viewport follows the player
+            viewport.update(&map, frame.size(), world.player_position(player_id));
+            player_slots.update(&world.scores());
+            for (y, cells) in viewport.rows().enumerate() {
+                for (x, pos) in cells.enumerate() {
+                    let object = map.get_object(&pos);
+                    let (glyph, style) = theme.draw(object.type_, player_slots.get(object.id));
+                    frame.put(x as u16 + 1, y as u16 + 1, glyph, style);
+                }
+            }
+
+            for (i, event) in world_state
+                .events
                 .iter()
                 .rev()
-                .take(world.height())
+                .take(viewport.height())
                 .enumerate()
             {
-                write!(
-                    stdout,
-                    "{}{}\r\n",
-                    termion::cursor::Goto(world.width() as u16 + 2, 1 + i as u16),
-                    log
-                )?;
+                frame.text(viewport.panel_x(), 1 + i as u16, &describe_logged(event));
             }
+            let lives = format!("Lives: {}", world.lives(player_id));
+            frame.text(1, viewport.height() as u16 + 1, &lives);
         }
 
-        stdout.flush()?;
+        renderer.draw(frame)?;
 
         sleep(Duration::from_millis(100)).await;
     }
+    // back on the main screen, where the outcome stays after the game is gone
+    drop(renderer);
     let world = world.lock().await;
     if !world.can_play(player_id) {
         let world_state = world.get_state();
-        write!(
-            stdout,
-            "{}{}{}",
-            termion::cursor::Goto((world.width() as u16) / 2 - 3, (world.height() / 2) as u16),
-            if world_state.dead_players.contains(&player_id) {
-                "YOU DIED!"
-            } else {
-                "YOU WON!"
-            },
-            termion::cursor::Goto(world.width() as u16, world.height() as u16)
-        )?;
+        if world_state.dead_players.contains(&player_id) {
+            println!("YOU DIED!");
+        } else {
+            println!("YOU WON!");
+        }
     }
     Ok(())
 }
@@ -111,18 +122,58 @@ async fn show_map_loop(
 #[command(author, version, about, long_about = None)]
 #[clap(verbatim_doc_comment)]
 struct Args {
-    /// Width of the world
+    /// Width of the generated world
     #[arg(short = 'x', default_value_t = 50)]
     width: usize,
-    /// Height of the world
+    /// Height of the generated world
     #[arg(short = 'y', default_value_t = 20)]
     height: usize,
-    /// Mob count
+    /// Maze generator: backtracker, prim or arena
+    #[arg(short = 'g', default_value_t = MazeGenerator::Backtracker)]
+    generator: MazeGenerator,
+    /// Seed of the generated maze and the world, random if not set
+    #[arg(short = 's')]
+    seed: Option<u64>,
+    /// Play one of the built-in or loaded mazes instead of a generated one
+    #[arg(long)]
+    maze: Option<String>,
+    /// Directory with additional maze files, named by their file stem
+    #[arg(long)]
+    maze_dir: Option<PathBuf>,
+    /// Random walker count
     #[arg(short = 'm', default_value_t = 10)]
     mob_cnt: usize,
+    /// Mobs running after players
+    #[arg(long, default_value_t = 0)]
+    chasers: usize,
+    /// Mobs walking fixed routes
+    #[arg(long, default_value_t = 0)]
+    patrollers: usize,
+    /// Mobs staying close to candies
+    #[arg(long, default_value_t = 0)]
+    guards: usize,
 
     #[arg(short = 'c', default_value_t = 5)]
     candy_cnt: usize,
+    /// Pickups of every kind: speed, shield, multi-shot, freeze and key
+    #[arg(short = 'p', default_value_t = 0)]
+    pickups: usize,
+    /// Length of one world tick in milliseconds
+    #[arg(
+        short = 't',
+        default_value_t = DEFAULT_TICK_MS,
+        value_parser = clap::value_parser!(u64).range(MIN_TICK_MS..=MAX_TICK_MS),
+    )]
+    tick_ms: u64,
+    /// How many times the player can die
+    #[arg(short = 'l', default_value_t = DEFAULT_LIVES)]
+    lives: u32,
+    /// Seconds before the player comes back after losing a life
+    #[arg(long, default_value_t = DEFAULT_RESPAWN_S)]
+    respawn_s: u64,
+    /// Theme: default, ascii, mono or a theme file, ascii if the terminal doesn't expect UTF-8
+    #[arg(long)]
+    theme: Option<String>,
 }
 
 #[tokio::main]
@@ -130,21 +181,69 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     println!("{:?}", args);
 
-    let world = Arc::new(Mutex::new(World::new(
-        args.width,
-        args.height,
-        args.mob_cnt,
+    let mazes = match &args.maze_dir {
+        Some(dir) => MazeLibrary::with_dir(dir)?,
+        None => MazeLibrary::builtin(),
+    };
+    let theme = match &args.theme {
+        Some(name) => Theme::load(name)?,
+        None => Theme::detect(),
+    };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let map = match &args.maze {
+        Some(maze_name) => mazes.map(maze_name)?,
+        None => Map::generate(&MazeSpec {
+            generator: args.generator,
+            width: args.width,
+            height: args.height,
+            seed,
+        }),
+    };
+
+    let mobs = [
+        MobConfig::new(MobKind::Walker, args.mob_cnt),
+        MobConfig::new(MobKind::Chaser, args.chasers),
+        MobConfig::new(MobKind::Patroller, args.patrollers),
+        MobConfig::new(MobKind::Guard, args.guards),
+    ];
+    let pickups = PickupKind::ALL.map(|kind| PickupConfig::new(kind, args.pickups));
+    check_capacity(
+        &map,
+        mob_count(&mobs),
         args.candy_cnt,
-    )));
-    let player_id = world.lock().await.spawn_player("local");
+        pickup_count(&pickups),
+    )?;
+
+    let world = Arc::new(Mutex::new(
+        World::with_seed(
+            map,
+            &mobs,
+            args.candy_cnt,
+            &pickups,
+            GameMode::Classic,
+            LivesConfig::new(
+                args.lives,
+                args.respawn_s.saturating_mul(1000),
+                args.tick_ms,
+            ),
+            seed,
+        )
+        .with_tick_ms(args.tick_ms),
+    ));
+    let player_id = world
+        .lock()
+        .await
+        .spawn_player("local")
+        .context("No room for the player in the maze")?;
     let stop = Arc::new(AtomicBool::new(false));
 
-    run_world(world.clone());
+    run_world(world.clone(), Duration::from_millis(args.tick_ms));
 
     let world_clone = world.clone();
     let stop_clone = stop.clone();
     let show_task = tokio::spawn(async move {
-        let _ = show_map_loop(world_clone, stop_clone, player_id).await;
+        let _ = show_map_loop(world_clone, stop_clone, player_id, theme).await;
     });
 
     let read_task = tokio::spawn(async move {